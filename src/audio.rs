@@ -0,0 +1,80 @@
+//! # Audio API
+//!
+//! Includes the structs that represent a response from the transcription API, which turns speech
+//! into text with Whisper.
+//!
+//! Audio is uploaded as `multipart/form-data` instead of JSON. The other parameters are sent as text
+//! fields next to the file.
+use std::{fmt::Debug, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Request;
+
+#[derive(Debug, Deserialize, Serialize)]
+/// Represents a response from the transcription API.
+pub struct TranscriptionResponse {
+    pub text: String,
+}
+
+impl TryFrom<serde_json::Value> for TranscriptionResponse {
+    type Error = serde_json::Error;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        Self::deserialize(value)
+    }
+}
+
+impl FromStr for TranscriptionResponse {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+#[derive(Clone)]
+/// An audio file uploaded with a request.
+pub(crate) struct AudioFile {
+    pub(crate) bytes: Vec<u8>,
+    /// OpenAI detects the format from the extension, so it has to match the contents.
+    pub(crate) filename: String,
+}
+
+impl Debug for AudioFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AudioFile")
+            .field("bytes", &self.bytes.len())
+            .field("filename", &self.filename)
+            .finish()
+    }
+}
+
+impl<T> Request<T> {
+    /// Returns the JSON parameters as the text fields of a form.
+    pub(crate) fn form_fields(&self) -> Vec<(String, String)> {
+        let params: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(&self.to_send).unwrap_or_default();
+
+        params
+            .into_iter()
+            .map(|(name, value)| match value {
+                serde_json::Value::String(value) => (name, value),
+                value => (name, value.to_string()),
+            })
+            .collect()
+    }
+
+    pub(crate) fn multipart(&self, file: &AudioFile) -> reqwest::multipart::Form {
+        let form = self
+            .form_fields()
+            .into_iter()
+            .fold(reqwest::multipart::Form::new(), |form, (name, value)| {
+                form.text(name, value)
+            });
+        let part =
+            reqwest::multipart::Part::bytes(file.bytes.clone()).file_name(file.filename.clone());
+
+        form.part("file", part)
+    }
+}
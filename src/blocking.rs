@@ -0,0 +1,194 @@
+//! Sending requests without an async runtime, enabled with the `blocking` feature.
+
+use once_cell::sync::OnceCell;
+
+use crate::{
+    audio, chat, completion, embedding, error_from_response, image, moderation, parse_choices,
+    parse_json, responses, AudioState, ChatState, CompletionState, EmbeddingState, ImageState,
+    ModerationState, RawResponse, Request, ResponsesState, SendRequestError, CHAT_PATH,
+    COMPLETION_PATH, EMBEDDING_PATH, IMAGE_PATH, MODERATION_PATH, RESPONSES_PATH,
+    TRANSCRIPTION_PATH,
+};
+
+static RQCLIENT: OnceCell<reqwest::blocking::Client> = OnceCell::new();
+
+impl<T> Request<T> {
+    fn post_blocking(self, path: &str) -> Result<RawResponse, SendRequestError> {
+        self.check_not_streaming()?;
+        self.check_blocking_config()?;
+
+        let log = self
+            .config
+            .log_file
+            .clone()
+            .map(|file| crate::request_log::RequestLog::new(file, self.to_send.clone()));
+        let mut attempt = 0;
+
+        loop {
+            let resp = self.send_raw_blocking(path)?;
+
+            let status = resp.status();
+            let headers = resp.headers().clone();
+            let body = resp.text()?;
+
+            if status.is_success() {
+                return Ok(RawResponse {
+                    status,
+                    headers,
+                    body,
+                    log,
+                });
+            }
+
+            let error = error_from_response(status, &body);
+
+            match self.retry_delay(attempt, status, &headers, &error) {
+                Some(delay) => {
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                None => {
+                    self.trace_error(path, status, &body);
+                    return Err(error);
+                }
+            }
+        }
+    }
+
+    /// Posts the request to `path` and parses the JSON response with `parse`.
+    fn send_json_blocking<R>(
+        self,
+        path: &str,
+        parse: fn(&serde_json::Value) -> Result<R, SendRequestError>,
+    ) -> Result<R, SendRequestError> {
+        let raw = self.post_blocking(path)?;
+        let json = raw.json()?;
+        let response = parse(&json)?;
+
+        raw.log_blocking(&json);
+        Ok(response)
+    }
+
+    /// Checks that no async client or transport was set, which would otherwise be skipped without a word.
+    fn check_blocking_config(&self) -> Result<(), SendRequestError> {
+        if self.config.transport.is_some() {
+            return Err(SendRequestError::UnsupportedConfig(
+                "Transports are async and can't be used by send_blocking, use send instead.",
+            ));
+        }
+        if self.config.client.is_some() {
+            return Err(SendRequestError::UnsupportedConfig(
+                "Clients set with client() are async and can't be used by send_blocking, use send instead.",
+            ));
+        }
+        Ok(())
+    }
+
+    fn send_raw_blocking(
+        &self,
+        path: &str,
+    ) -> Result<reqwest::blocking::Response, SendRequestError> {
+        self.trace_request(path);
+
+        let fresh_client;
+        let client = if self.config.fresh_client {
+            fresh_client = reqwest::blocking::Client::new();
+            &fresh_client
+        } else {
+            RQCLIENT.get_or_init(reqwest::blocking::Client::new)
+        };
+
+        let mut req = client.post(format!("{}{}", self.config.base_url, path));
+
+        for (name, value) in self.headers() {
+            req = req.header(name, value);
+        }
+
+        if let Some(timeout) = self.config.timeout {
+            req = req.timeout(timeout);
+        }
+
+        let req = match &self.file {
+            Some(file) => req.multipart(self.multipart_blocking(file)),
+            None => req.body(self.to_send.clone()),
+        };
+
+        Ok(req.send()?)
+    }
+
+    fn multipart_blocking(&self, file: &audio::AudioFile) -> reqwest::blocking::multipart::Form {
+        let form = self.form_fields().into_iter().fold(
+            reqwest::blocking::multipart::Form::new(),
+            |form, (name, value)| form.text(name, value),
+        );
+        let part = reqwest::blocking::multipart::Part::bytes(file.bytes.clone())
+            .file_name(file.filename.clone());
+
+        form.part("file", part)
+    }
+}
+
+impl Request<CompletionState> {
+    /// Sends the request, blocking the current thread until the response arrives.
+    ///
+    /// This must not be called from within an async runtime, use [`SendRequest::send`](crate::SendRequest::send) there instead.
+    pub fn send_blocking(self) -> Result<completion::CompletionResponse, SendRequestError> {
+        self.send_json_blocking(COMPLETION_PATH, parse_choices)
+    }
+}
+
+impl Request<ChatState> {
+    /// Sends the request, blocking the current thread until the response arrives.
+    ///
+    /// This must not be called from within an async runtime, use [`SendRequest::send`](crate::SendRequest::send) there instead.
+    pub fn send_blocking(self) -> Result<chat::ChatResponse, SendRequestError> {
+        self.check_messages()?;
+
+        self.send_json_blocking(CHAT_PATH, parse_choices)
+    }
+}
+
+impl Request<ResponsesState> {
+    /// Sends the request, blocking the current thread until the response arrives.
+    ///
+    /// This must not be called from within an async runtime, use [`SendRequest::send`](crate::SendRequest::send) there instead.
+    pub fn send_blocking(self) -> Result<responses::ResponsesResult, SendRequestError> {
+        self.send_json_blocking(RESPONSES_PATH, parse_json)
+    }
+}
+
+impl Request<EmbeddingState> {
+    /// Sends the request, blocking the current thread until the response arrives.
+    ///
+    /// This must not be called from within an async runtime, use [`SendRequest::send`](crate::SendRequest::send) there instead.
+    pub fn send_blocking(self) -> Result<embedding::EmbeddingResponse, SendRequestError> {
+        self.send_json_blocking(EMBEDDING_PATH, parse_json)
+    }
+}
+
+impl Request<ModerationState> {
+    /// Sends the request, blocking the current thread until the response arrives.
+    ///
+    /// This must not be called from within an async runtime, use [`SendRequest::send`](crate::SendRequest::send) there instead.
+    pub fn send_blocking(self) -> Result<moderation::ModerationResponse, SendRequestError> {
+        self.send_json_blocking(MODERATION_PATH, parse_json)
+    }
+}
+
+impl Request<ImageState> {
+    /// Sends the request, blocking the current thread until the response arrives.
+    ///
+    /// This must not be called from within an async runtime, use [`SendRequest::send`](crate::SendRequest::send) there instead.
+    pub fn send_blocking(self) -> Result<image::ImageResponse, SendRequestError> {
+        self.send_json_blocking(IMAGE_PATH, parse_json)
+    }
+}
+
+impl Request<AudioState> {
+    /// Sends the request, blocking the current thread until the response arrives.
+    ///
+    /// This must not be called from within an async runtime, use [`SendRequest::send`](crate::SendRequest::send) there instead.
+    pub fn send_blocking(self) -> Result<audio::TranscriptionResponse, SendRequestError> {
+        self.send_json_blocking(TRANSCRIPTION_PATH, parse_json)
+    }
+}
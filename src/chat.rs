@@ -9,60 +9,475 @@
 //! It will automatically remember the messages you send and the messages the model sends so the model can remember the conversation.
 //!
 //! See the [`ChatBuilder`] and [`Chat`] structs for more information.
-use std::{collections::VecDeque, error::Error};
-use tokio::sync::Mutex;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    error::Error,
+    fmt::Display,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{Mutex, MutexGuard};
 
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 
-use crate::SendRequest;
+use crate::{ParseError, SendRequest, Stop};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// Represents one of the messages sent to or received from the chat API.
 pub struct ChatMessage {
     pub role: Role,
-    pub content: Option<String>,
+    pub content: Option<Content>,
+    /// An optional name for the participant, to tell apart participants with the same role.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The function the model wants to call, if it decided to call one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<FunctionCall>,
+    /// The tools the model wants to call, if it decided to call any.
+    #[serde(default, skip_serializing_if = "no_tool_calls")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// The id of the tool call a [`Role::Tool`] message is answering.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    /// Creates a message with the given role and content.
+    pub fn new<T: Into<String>>(role: Role, content: T) -> Self {
+        Self {
+            role,
+            content: Some(Content::Text(content.into())),
+            name: None,
+            function_call: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    /// Creates a message from the user.
+    pub fn user<T: Into<String>>(content: T) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    /// Creates a system message, which instructs the model how to behave.
+    pub fn system<T: Into<String>>(content: T) -> Self {
+        Self::new(Role::System, content)
+    }
+
+    /// Creates a message from the assistant, e.g. to give the model examples of how it should reply.
+    pub fn assistant<T: Into<String>>(content: T) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    /// Creates a message from the user with text and an image, for models that accept images.
+    ///
+    /// `image_url` can be a regular URL or a base64 encoded `data:` URL.
+    pub fn with_image<T: Into<String>, U: Into<String>>(text: T, image_url: U) -> Self {
+        Self {
+            content: Some(Content::Parts(vec![
+                ContentPart::text(text),
+                ContentPart::image_url(image_url),
+            ])),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the content if it's plain text.
+    pub fn text(&self) -> Option<&str> {
+        self.content.as_ref().and_then(Content::as_text)
+    }
 }
 
 impl Default for ChatMessage {
     fn default() -> Self {
         Self {
             role: Role::User,
-            content: Some(String::new()),
+            content: Some(Content::Text(String::new())),
+            name: None,
+            function_call: None,
+            tool_calls: None,
+            tool_call_id: None,
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-/// Represents the usage information returned by the chat API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+/// The content of a [`ChatMessage`], either plain text or a list of parts.
+///
+/// Plain text is sent as a string, so it works with every model.
+pub enum Content {
+    Text(String),
+    /// Text and images, only accepted by models with vision.
+    Parts(Vec<ContentPart>),
+}
+
+impl Content {
+    /// Returns the text if this is plain text.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Content::Text(text) => Some(text),
+            Content::Parts(_) => None,
+        }
+    }
+
+    /// Returns the plain text or every text part, leaving out images.
+    pub fn texts(&self) -> Vec<&str> {
+        match self {
+            Content::Text(text) => vec![text],
+            Content::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    ContentPart::ImageUrl { .. } => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<String> for Content {
+    fn from(text: String) -> Self {
+        Content::Text(text)
+    }
+}
+
+impl From<&str> for Content {
+    fn from(text: &str) -> Self {
+        Content::Text(text.to_string())
+    }
+}
+
+impl From<Vec<ContentPart>> for Content {
+    fn from(parts: Vec<ContentPart>) -> Self {
+        Content::Parts(parts)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// One part of a [`Content::Parts`] message.
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+impl ContentPart {
+    /// Creates a text part.
+    pub fn text<T: Into<String>>(text: T) -> Self {
+        ContentPart::Text { text: text.into() }
+    }
+
+    /// Creates an image part with the default level of detail.
+    pub fn image_url<T: Into<String>>(url: T) -> Self {
+        ContentPart::ImageUrl {
+            image_url: ImageUrl {
+                url: url.into(),
+                detail: None,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// An image in a [`ContentPart::ImageUrl`].
+pub struct ImageUrl {
+    pub url: String,
+    /// How closely the model looks at the image: `"low"`, `"high"` or `"auto"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+/// Represents the usage information returned by the chat and completion APIs.
 pub struct Usage {
     pub prompt_tokens: u32,
+    /// Missing from endpoints that don't generate text, like embeddings, where it is 0.
+    #[serde(default)]
     pub completion_tokens: u32,
     pub total_tokens: u32,
 }
 
+impl Usage {
+    /// Returns `true` if `prompt_tokens + completion_tokens == total_tokens`.
+    ///
+    /// OpenAI always reports consistent usage, but some OpenAI-compatible backends don't.
+    pub fn is_consistent(&self) -> bool {
+        self.prompt_tokens.checked_add(self.completion_tokens) == Some(self.total_tokens)
+    }
+
+    /// Returns `total_tokens`, or `None` if it doesn't add up (see [`is_consistent`]).
+    ///
+    /// [`is_consistent`]: #method.is_consistent
+    pub fn checked_total_tokens(&self) -> Option<u32> {
+        self.is_consistent().then_some(self.total_tokens)
+    }
+
+    /// Estimates what this usage cost in US dollars with `model`, or `None` if its price isn't known.
+    ///
+    /// This uses OpenAI's list prices, use a [`PricingTable`](crate::pricing::PricingTable) to supply your own.
+    pub fn estimated_cost(&self, model: &str) -> Option<f64> {
+        crate::pricing::DEFAULT_PRICES.cost(model, self)
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 /// Represents the choice object returned by the chat API.
 pub struct ChatChoice {
+    /// Some OpenAI-compatible servers leave out `index` and `object`, which are then 0 and empty.
+    #[serde(default)]
     pub index: u32,
     pub message: ChatMessage,
-    pub finish_reason: Option<String>,
+    #[serde(default)]
+    pub finish_reason: FinishReason,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Why the model stopped generating a choice.
+///
+/// Used by both the chat and completion APIs. Reasons this crate doesn't know about are kept in [`FinishReason::Other`].
+pub enum FinishReason {
+    /// The model finished naturally or hit a stop sequence.
+    Stop,
+    /// The output was cut off by `max_tokens` or the context window.
+    Length,
+    /// Content was omitted by the content filter.
+    ContentFilter,
+    /// The model called a function.
+    FunctionCall,
+    /// The model called one or more tools.
+    ToolCalls,
+    /// No reason was given, e.g. because the choice isn't finished yet.
+    #[default]
+    Null,
+    /// A reason this crate doesn't know about yet.
+    Other(String),
+}
+
+impl FinishReason {
+    fn as_str(&self) -> Option<&str> {
+        Some(match self {
+            FinishReason::Stop => "stop",
+            FinishReason::Length => "length",
+            FinishReason::ContentFilter => "content_filter",
+            FinishReason::FunctionCall => "function_call",
+            FinishReason::ToolCalls => "tool_calls",
+            FinishReason::Null => return None,
+            FinishReason::Other(reason) => reason,
+        })
+    }
+}
+
+impl From<Option<String>> for FinishReason {
+    fn from(reason: Option<String>) -> Self {
+        match reason.as_deref() {
+            None => FinishReason::Null,
+            Some("stop") => FinishReason::Stop,
+            Some("length") => FinishReason::Length,
+            Some("content_filter") => FinishReason::ContentFilter,
+            Some("function_call") => FinishReason::FunctionCall,
+            Some("tool_calls") => FinishReason::ToolCalls,
+            Some(_) => FinishReason::Other(reason.unwrap()),
+        }
+    }
+}
+
+impl Serialize for FinishReason {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.as_str().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FinishReason {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer).map(Self::from)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 /// Represents a response from the chat API.
 pub struct ChatResponse {
     pub id: String,
+    #[serde(default)]
     pub object: String,
     pub created: u64,
+    /// The model that generated the response, which may be a dated snapshot of the requested one.
+    #[serde(default)]
+    pub model: String,
     pub choices: Vec<ChatChoice>,
     pub usage: Usage,
 }
+
+impl ChatResponse {
+    /// Returns when the response was created, converted from the unix timestamp in `created`.
+    pub fn created_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.created)
+    }
+
+    /// Returns the message of the first choice, or `None` if the response has no choices.
+    pub fn first_message(&self) -> Option<&ChatMessage> {
+        self.choices.first().map(|choice| &choice.message)
+    }
+
+    /// Returns the message of every choice, e.g. to pick between the candidates generated with `n`.
+    pub fn messages(&self) -> Vec<&ChatMessage> {
+        self.choices.iter().map(|choice| &choice.message).collect()
+    }
+}
+
+impl Display for ChatResponse {
+    /// Writes the text of the first message, which is empty if there are no choices or the model
+    /// only called tools.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let content = self
+            .first_message()
+            .and_then(|message| message.content.as_ref());
+
+        for text in content.map(Content::texts).unwrap_or_default() {
+            f.write_str(text)?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<serde_json::Value> for ChatResponse {
+    type Error = serde_json::Error;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        Self::deserialize(value)
+    }
+}
+
+impl FromStr for ChatResponse {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+/// Represents one of the chunks of a streamed chat response.
+pub enum ChatStreamChunk {
+    /// The next part of the generated messages.
+    Delta(ChatStreamDelta),
+    /// The token usage of the whole request, sent as the last chunk when
+    /// [`RequestBuilder::stream_usage`](crate::RequestBuilder::stream_usage) is enabled.
+    Usage(Usage),
+}
+
+impl ChatStreamChunk {
+    /// Returns the choices of a delta. The usage chunk has none.
+    pub fn choices(&self) -> &[ChatStreamChoice] {
+        match self {
+            ChatStreamChunk::Delta(delta) => &delta.choices,
+            ChatStreamChunk::Usage(_) => &[],
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ChatStreamChunk {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        // with include_usage every chunk has a `usage` field, which is only set on the last one
+        #[derive(Deserialize)]
+        struct Chunk {
+            #[serde(flatten)]
+            delta: ChatStreamDelta,
+            usage: Option<Usage>,
+        }
+
+        let chunk = Chunk::deserialize(deserializer)?;
+
+        Ok(match chunk.usage {
+            Some(usage) if chunk.delta.choices.is_empty() => ChatStreamChunk::Usage(usage),
+            _ => ChatStreamChunk::Delta(chunk.delta),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+/// Represents a chunk of a streamed chat response that continues the generated messages.
+pub struct ChatStreamDelta {
+    pub id: String,
+    #[serde(default)]
+    pub object: String,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<ChatStreamChoice>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+/// Represents the choice object in a streamed chat response.
+pub struct ChatStreamChoice {
+    #[serde(default)]
+    pub index: u32,
+    pub delta: ChatDelta,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+/// A streamed chat response once all of its chunks were received, see [`Chat::stream_full_response`].
+pub struct StreamedResponse {
+    /// The message assembled from the first choice.
+    pub message: ChatMessage,
+    /// The last finish reason sent for the first choice.
+    pub finish_reason: FinishReason,
+    /// The token usage, which is only sent when `stream_usage` is enabled.
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+/// Represents the part of a message added by a streamed chunk.
+pub struct ChatDelta {
+    pub role: Option<Role>,
+    pub content: Option<String>,
+    /// Pieces of the tool calls the model is making, to be joined by their `index`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+/// Represents the part of a tool call added by a streamed chunk.
+///
+/// The first piece of a call carries its `id`, type and function name, the following ones only
+/// add to the arguments.
+pub struct ToolCallDelta {
+    /// Which of the message's tool calls this piece belongs to.
+    pub index: u32,
+    pub id: Option<String>,
+    #[serde(rename = "type")]
+    pub tool_type: Option<String>,
+    pub function: Option<FunctionCallDelta>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+/// Represents the part of a function call added by a streamed chunk.
+pub struct FunctionCallDelta {
+    pub name: Option<String>,
+    pub arguments: Option<String>,
+}
+
 #[derive(Debug, Clone)]
 /// Represents one of the roles that can be used in the chat API.
 pub enum Role {
     User,
     Assistant,
     System,
+    Tool,
+    /// The result of a function call, used before function calling was replaced by tools.
+    Function,
 }
 
 impl Serialize for Role {
@@ -85,14 +500,30 @@ impl<'de> Deserialize<'de> for Role {
     }
 }
 
-impl ToString for Role {
-    fn to_string(&self) -> String {
-        match self {
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
             Role::User => "user",
             Role::Assistant => "assistant",
             Role::System => "system",
+            Role::Tool => "tool",
+            Role::Function => "function",
+        })
+    }
+}
+
+impl FromStr for Role {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "assistant" => Ok(Role::Assistant),
+            "system" => Ok(Role::System),
+            "tool" => Ok(Role::Tool),
+            "function" => Ok(Role::Function),
+            _ => Err(ParseError::new("role", s)),
         }
-        .to_string()
     }
 }
 
@@ -100,25 +531,227 @@ impl TryFrom<&str> for Role {
     type Error = Box<dyn Error>;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value {
-            "user" => Ok(Role::User),
-            "assistant" => Ok(Role::Assistant),
-            "system" => Ok(Role::System),
-            _ => Err("Invalid Role".into()),
+        Ok(value.parse()?)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Describes a tool the model may call. Functions are the only kind of tool so far.
+pub struct Tool {
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: FunctionDef,
+}
+
+impl Tool {
+    /// Creates a tool that calls `function`.
+    pub fn function(function: FunctionDef) -> Self {
+        Self {
+            tool_type: "function".to_string(),
+            function,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Controls which (if any) tool the model calls.
+pub enum ToolChoice {
+    /// The model will not call a tool.
+    None,
+    /// The model decides whether to call a tool.
+    Auto,
+    /// The model must call at least one tool.
+    Required,
+    /// The model must call the function with this name.
+    Function(String),
+}
+
+impl Serialize for ToolChoice {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            ToolChoice::None => serializer.serialize_str("none"),
+            ToolChoice::Auto => serializer.serialize_str("auto"),
+            ToolChoice::Required => serializer.serialize_str("required"),
+            ToolChoice::Function(name) => serde_json::json!({
+                "type": "function",
+                "function": { "name": name },
+            })
+            .serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ToolChoice {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = serde_json::Value::deserialize(deserializer)?;
+
+        match value.as_str() {
+            Some("none") => return Ok(ToolChoice::None),
+            Some("auto") => return Ok(ToolChoice::Auto),
+            Some("required") => return Ok(ToolChoice::Required),
+            _ => {}
+        }
+
+        value["function"]["name"]
+            .as_str()
+            .map(|name| ToolChoice::Function(name.to_string()))
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid tool_choice: {value}")))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// The format the model has to answer in.
+pub enum ResponseFormat {
+    /// Plain text, the default.
+    Text,
+    /// JSON mode, which guarantees the answer is valid JSON.
+    ///
+    /// The messages still have to ask for JSON, otherwise the model may generate whitespace until it runs out of tokens.
+    JsonObject,
+    /// Structured outputs, which make the answer follow a JSON schema.
+    ///
+    /// Only the gpt-4o generation and later support this, which `try_build_chat` checks for the known older models.
+    JsonSchema { json_schema: JsonSchema },
+}
+
+impl ResponseFormat {
+    /// Creates a [`ResponseFormat::JsonSchema`] with the given name and schema.
+    pub fn json_schema<T: Into<String>>(name: T, schema: serde_json::Value) -> Self {
+        ResponseFormat::JsonSchema {
+            json_schema: JsonSchema {
+                name: name.into(),
+                description: None,
+                schema,
+                strict: None,
+            },
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The schema the answer has to follow with [`ResponseFormat::JsonSchema`].
+pub struct JsonSchema {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub schema: serde_json::Value,
+    /// Whether the model has to follow the schema exactly, which only supports a subset of JSON schema.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Describes a function the model may call.
+pub struct FunctionDef {
+    pub name: String,
+    pub description: String,
+    /// The JSON schema of the function's arguments.
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Controls which (if any) function the model calls.
+pub enum FunctionCallSetting {
+    /// The model will not call a function.
+    None,
+    /// The model decides whether to call a function.
+    Auto,
+    /// The model must call the function with this name.
+    Function(String),
+}
+
+impl Serialize for FunctionCallSetting {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            FunctionCallSetting::None => serializer.serialize_str("none"),
+            FunctionCallSetting::Auto => serializer.serialize_str("auto"),
+            FunctionCallSetting::Function(name) => {
+                serde_json::json!({ "name": name }).serialize(serializer)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A function call chosen by the model.
+pub struct FunctionCall {
+    pub name: String,
+    /// The arguments as a JSON string. The model doesn't always produce valid JSON, so check before using them.
+    pub arguments: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A tool call chosen by the model.
+///
+/// Answer it with [`Chat::submit_tool_result`] or a [`Role::Tool`] message carrying the same `id`.
+pub struct ToolCall {
+    pub id: String,
+    /// The kind of tool, currently always `"function"`.
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: FunctionCall,
+}
+
+/// Turns the joined pieces of a streamed tool call into a [`ToolCall`], if the stream sent all of it.
+fn assemble_tool_call(call: ToolCallDelta) -> Option<ToolCall> {
+    let function = call.function?;
+
+    Some(ToolCall {
+        id: call.id?,
+        tool_type: call.tool_type.unwrap_or_else(|| "function".to_string()),
+        function: FunctionCall {
+            name: function.name?,
+            arguments: function.arguments.unwrap_or_default(),
+        },
+    })
+}
+
+/// The API rejects an empty `tool_calls` array, so it's left out like a missing one.
+fn no_tool_calls(tool_calls: &Option<Vec<ToolCall>>) -> bool {
+    tool_calls.as_ref().map_or(true, Vec::is_empty)
+}
+
 // ----------------------------------------------------
 // new unstable chat thing
 
+/// The tokens [`ChatBuilder::fit_context_window`] keeps free for the reply when `max_tokens` isn't set.
+pub const DEFAULT_REPLY_TOKENS: usize = 1024;
+
+/// Fragments of model names that only work with endpoints other than chat.
+const NON_CHAT_MODELS: &[&str] = &[
+    "davinci",
+    "curie",
+    "babbage",
+    "-instruct",
+    "embedding",
+    "moderation",
+    "whisper",
+    "dall-e",
+    "tts",
+];
+
 /// Builds a [`Chat`] struct for initiating a chat session.
 pub struct ChatBuilder {
     system: ChatMessage,
     chat_parameters: ChatParameters,
     api_key: String,
+    config: crate::RequestConfig,
     model: crate::ChatModel,
     len: usize,
+    max_history_messages: Option<usize>,
+    max_context_tokens: Option<usize>,
+    fit_context_window: bool,
+    stream_usage: bool,
 }
 
 impl ChatBuilder {
@@ -132,18 +765,163 @@ impl ChatBuilder {
         ChatBuilder {
             model,
             api_key,
+            config: crate::RequestConfig::default(),
             system: default_msg,
             chat_parameters: ChatParameters::default(),
             len: 5,
+            max_history_messages: None,
+            max_context_tokens: None,
+            fit_context_window: false,
+            stream_usage: false,
         }
     }
 
-    /// Sets the amount of user messages that are stored in the chat session.
+    /// Creates a new [`ChatBuilder`], reading the API key from the `OPENAI_API_KEY` environment variable.
+    ///
+    /// Returns [`BuildError::MissingApiKey`](crate::BuildError::MissingApiKey) if it is not set or empty.
+    pub fn from_env(model: crate::ChatModel) -> Result<Self, crate::BuildError> {
+        Ok(Self::new(model, crate::api_key_from_env()?))
+    }
+
+    /// Sets how many user messages are kept in the chat history, 5 by default.
+    ///
+    /// Each user message is kept together with the replies to it. When a new message would go over
+    /// the limit, the oldest user message and its replies are dropped. The system message is always kept.
+    ///
+    /// To cap the history at an exact number of messages instead, use [`max_history_messages`].
+    ///
+    /// [`max_history_messages`]: #method.max_history_messages
     pub fn len(mut self, len: usize) -> Self {
         self.len = len;
         self
     }
 
+    /// Keeps at most `max` messages in the history, not counting the system message.
+    ///
+    /// Every message counts, whatever its role. When sending a message would go over the limit, the
+    /// oldest messages are dropped one by one until it fits, and the history is trimmed to `max` again
+    /// once the reply is added. The message being sent is always kept, so `0` behaves like `1`.
+    ///
+    /// A message that called tools is dropped together with the tool results that answer it, so the
+    /// history can end up shorter than `max`. The latest tool call and its results are always kept.
+    ///
+    /// This applies on top of [`len`], so whichever limit is stricter wins.
+    ///
+    /// [`len`]: #method.len
+    pub fn max_history_messages(mut self, max: usize) -> Self {
+        self.max_history_messages = Some(max);
+        self
+    }
+
+    /// Also drops the oldest messages while the history is estimated to be longer than `tokens`.
+    ///
+    /// Token counts are estimated from the message lengths unless the `tiktoken` feature is enabled,
+    /// so leave some headroom below the model's context window.
+    /// The latest user message is always sent, even if it doesn't fit.
+    pub fn max_context_tokens(mut self, tokens: usize) -> Self {
+        self.max_context_tokens = Some(tokens);
+        self
+    }
+
+    /// Drops the oldest messages while the history doesn't fit the model's context window
+    /// (see [`ChatModel::context_window`]) together with the [`max_tokens`] reserved for the reply.
+    ///
+    /// Without [`max_tokens`], [`DEFAULT_REPLY_TOKENS`] are reserved so the reply still has room.
+    /// The estimate includes the few tokens of formatting OpenAI adds to every message.
+    ///
+    /// This works like [`max_context_tokens`] with a budget worked out from the model, and both can be combined.
+    ///
+    /// [`ChatModel::context_window`]: ../enum.ChatModel.html#method.context_window
+    /// [`max_tokens`]: #method.max_tokens
+    /// [`max_context_tokens`]: #method.max_context_tokens
+    pub fn fit_context_window(mut self, fit: bool) -> Self {
+        self.fit_context_window = fit;
+        self
+    }
+
+    /// Asks for the token usage of streamed responses, see [`RequestBuilder::stream_usage`].
+    ///
+    /// It is only sent with [`Chat::stream_response`] and [`Chat::stream_full_response`], which add
+    /// the usage to [`Chat::total_usage`] like the other methods do.
+    ///
+    /// [`RequestBuilder::stream_usage`]: ../struct.RequestBuilder.html#method.stream_usage
+    pub fn stream_usage(mut self, include_usage: bool) -> Self {
+        self.stream_usage = include_usage;
+        self
+    }
+
+    /// Sets the base URL requests are sent to, see [`RequestBuilder::base_url`].
+    ///
+    /// [`RequestBuilder::base_url`]: ../struct.RequestBuilder.html#method.base_url
+    pub fn base_url<T: ToString>(mut self, base_url: T) -> Self {
+        self.config.base_url(base_url);
+        self
+    }
+
+    /// Sets the `User-Agent` header, see [`RequestBuilder::user_agent`].
+    ///
+    /// [`RequestBuilder::user_agent`]: ../struct.RequestBuilder.html#method.user_agent
+    pub fn user_agent<T: ToString>(mut self, user_agent: T) -> Self {
+        self.config.user_agent(user_agent);
+        self
+    }
+
+    /// Sets the `OpenAI-Organization` header, see [`RequestBuilder::organization`].
+    ///
+    /// [`RequestBuilder::organization`]: ../struct.RequestBuilder.html#method.organization
+    pub fn organization<T: Into<String>>(mut self, organization: T) -> Self {
+        self.config.organization(organization);
+        self
+    }
+
+    /// Adds a header to the chat's requests, see [`RequestBuilder::header`].
+    ///
+    /// [`RequestBuilder::header`]: ../struct.RequestBuilder.html#method.header
+    pub fn header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.config.header(name, value);
+        self
+    }
+
+    /// Sends the chat's requests with the given client, see [`RequestBuilder::client`].
+    ///
+    /// [`RequestBuilder::client`]: ../struct.RequestBuilder.html#method.client
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.config.client(client);
+        self
+    }
+
+    /// Sends the chat's requests with the given transport, see [`RequestBuilder::transport`].
+    ///
+    /// [`RequestBuilder::transport`]: ../struct.RequestBuilder.html#method.transport
+    pub fn transport<T: crate::transport::Transport + 'static>(mut self, transport: T) -> Self {
+        self.config.transport(transport);
+        self
+    }
+
+    /// Sets a timeout for each of the chat's requests, see [`RequestBuilder::timeout`].
+    ///
+    /// [`RequestBuilder::timeout`]: ../struct.RequestBuilder.html#method.timeout
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.config.timeout(timeout);
+        self
+    }
+
+    /// Retries the chat's requests when they fail, see [`RequestBuilder::retry`].
+    ///
+    /// [`RequestBuilder::retry`]: ../struct.RequestBuilder.html#method.retry
+    pub fn retry(mut self, retry: crate::RetryConfig) -> Self {
+        self.config.retry(retry);
+        self
+    }
+
+    /// Logs every request and response of the chat to a JSONL file, see [`RequestBuilder::log_to`].
+    ///
+    /// [`RequestBuilder::log_to`]: ../struct.RequestBuilder.html#method.log_to
+    pub fn log_to<P: Into<std::path::PathBuf>>(mut self, path: P) -> Self {
+        self.config.log_to(path);
+        self
+    }
+
     /// Sets the system message that is sent to the chat API
     pub fn system(mut self, system: ChatMessage) -> Self {
         self.system = system;
@@ -151,8 +929,8 @@ impl ChatBuilder {
     }
 
     /// Sets the temperature
-    pub fn temperature(mut self, temperature: f32) -> Self {
-        self.chat_parameters.temperature = Some(temperature);
+    pub fn temperature<T: Into<f64>>(mut self, temperature: T) -> Self {
+        self.chat_parameters.temperature = Some(temperature.into());
         self
     }
 
@@ -162,39 +940,140 @@ impl ChatBuilder {
         self
     }
 
+    /// Clears a limit set with [`max_tokens`] so the model can use the rest of its context window.
+    ///
+    /// This is also the default: [`ChatParameters`] leaves `max_tokens` unset unless it is configured,
+    /// so the field is omitted from the request and OpenAI applies its own default.
+    ///
+    /// [`max_tokens`]: #method.max_tokens
+    pub fn no_max_tokens(mut self) -> Self {
+        self.chat_parameters.max_tokens = None;
+        self
+    }
+
     /// Sets the top_p parameter
-    pub fn top_p(mut self, top_p: f32) -> Self {
-        self.chat_parameters.top_p = Some(top_p);
+    pub fn top_p<T: Into<f64>>(mut self, top_p: T) -> Self {
+        self.chat_parameters.top_p = Some(top_p.into());
         self
     }
 
     /// Sets the presence penalty
-    pub fn presence_penalty(mut self, presence_penalty: f32) -> Self {
-        self.chat_parameters.presence_penalty = Some(presence_penalty);
+    pub fn presence_penalty<T: Into<f64>>(mut self, presence_penalty: T) -> Self {
+        self.chat_parameters.presence_penalty = Some(presence_penalty.into());
         self
     }
 
     /// Sets the frequency penalty
-    pub fn frequency_penalty(mut self, frequency_penalty: f32) -> Self {
-        self.chat_parameters.frequency_penalty = Some(frequency_penalty);
+    pub fn frequency_penalty<T: Into<f64>>(mut self, frequency_penalty: T) -> Self {
+        self.chat_parameters.frequency_penalty = Some(frequency_penalty.into());
+        self
+    }
+
+    /// Sets the stop sequence, sent as an array just like [`RequestBuilder::stop`].
+    ///
+    /// [`RequestBuilder::stop`]: ../struct.RequestBuilder.html#method.stop
+    pub fn stop<T: ToString>(mut self, stop: T) -> Self {
+        self.chat_parameters.stop = Some(crate::stop_sequences([stop]));
+        self
+    }
+
+    /// Sets several stop sequences at once, see [`RequestBuilder::stop_sequences`].
+    ///
+    /// [`try_build`](#method.try_build) checks that there are no more than four.
+    ///
+    /// [`RequestBuilder::stop_sequences`]: ../struct.RequestBuilder.html#method.stop_sequences
+    pub fn stop_sequences<I, T>(mut self, stop: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: ToString,
+    {
+        self.chat_parameters.stop = Some(crate::stop_sequences(stop));
         self
     }
 
-    /// Sets the user
+    /// Sets how many replies are generated for each message, 1 by default.
+    ///
+    /// Only the first reply is added to the history. Use [`Chat::get_responses`] to see all of them.
+    ///
+    /// [`Chat::get_responses`]: ./struct.Chat.html#method.get_responses
+    pub fn n(mut self, n: u32) -> Self {
+        self.chat_parameters.n = Some(n);
+        self
+    }
+
+    /// Sets the default user sent with every request in the chat session.
     pub fn user(mut self, user: String) -> Self {
         self.chat_parameters.user = Some(user);
         self
     }
 
+    /// Sets the default metadata attached to every request in the chat session.
+    ///
+    /// Per-turn metadata can be added with [`Chat::get_response_with_metadata`].
+    ///
+    /// [`Chat::get_response_with_metadata`]: ./struct.Chat.html#method.get_response_with_metadata
+    pub fn metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.chat_parameters.metadata = Some(metadata);
+        self
+    }
+
+    /// Sets whether OpenAI stores the completions, which is required for metadata to show up in the dashboard.
+    pub fn store(mut self, store: bool) -> Self {
+        self.chat_parameters.store = Some(store);
+        self
+    }
+
+    /// Sets the tools the model may call in every request of the chat session.
+    ///
+    /// Replies that call a tool are kept in the history with their [`ToolCall`]s. Send the results back
+    /// with [`Chat::submit_tool_result`] and then [`Chat::get_response`].
+    pub fn tools(mut self, tools: Vec<Tool>) -> Self {
+        self.chat_parameters.tools = Some(tools);
+        self
+    }
+
+    /// Sets which (if any) tool the model calls, which requires [`tools`](#method.tools) to be set as well.
+    pub fn tool_choice(mut self, tool_choice: ToolChoice) -> Self {
+        self.chat_parameters.tool_choice = Some(tool_choice);
+        self
+    }
+
+    /// Builds the [`Chat`] struct, first checking that the model works with the chat endpoint
+    /// and that the parameters are within the ranges the API accepts.
+    ///
+    /// All of the named [`ChatModel`] variants pass. Other model strings pass unless they are known
+    /// to only work with another endpoint, like the davinci completion models.
+    ///
+    /// [`ChatModel`]: ../enum.ChatModel.html
+    pub fn try_build(self) -> Result<Chat, crate::BuildError> {
+        let model = self.model.to_string();
+
+        if NON_CHAT_MODELS.iter().any(|name| model.contains(name)) {
+            return Err(crate::BuildError::NotAChatModel(model));
+        }
+
+        let params = &self.chat_parameters;
+        crate::validate_ranges(
+            params.temperature,
+            params.top_p,
+            params.presence_penalty,
+            params.frequency_penalty,
+        )?;
+
+        if let Some(stop) = &params.stop {
+            crate::validate_stop_sequences(stop.len())?;
+        }
+
+        if params.tool_choice.is_some() && params.tools.is_none() {
+            return Err(crate::BuildError::ToolChoiceWithoutTools);
+        }
+
+        Ok(self.build())
+    }
+
     /// Builds the [`Chat`] struct.
     pub fn build(self) -> Chat {
-        Chat::new(
-            self.system,
-            self.model,
-            self.len,
-            self.api_key,
-            self.chat_parameters,
-        )
+        Chat::new(self)
     }
 }
 
@@ -203,19 +1082,37 @@ impl ChatBuilder {
 #[derive(Default)]
 pub struct ChatParameters {
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub temperature: Option<f32>,
+    pub temperature: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub top_p: Option<f32>,
+    pub top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub presence_penalty: Option<f32>,
+    pub frequency_penalty: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub frequency_penalty: Option<f32>,
+    pub stop: Option<Stop>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
 }
 
+/// An iterator over a [`Chat`]'s message history, starting with the system message.
+pub type Messages<'a> = std::iter::Chain<
+    std::iter::Once<&'a ChatMessage>,
+    std::collections::vec_deque::Iter<'a, ChatMessage>,
+>;
+
 /// A struct that represents a chat session
 /// This struct makes it easy to interact with the chat api, as well as remembering messages.
 /// This struct guarantees that messages are sent and stored in the order that [`ask`] is called.
@@ -229,82 +1126,494 @@ pub struct ChatParameters {
 /// You can build a new chat session with [`ChatBuilder`].
 ///
 /// [`ChatBuilder`]: ./struct.ChatBuilder.html
+///
+/// ## Sharing a chat
+///
+/// Cloning a [`Chat`] is cheap and the clones share the same conversation, so it can be handed to
+/// several tasks without wrapping it in an `Arc`. Messages added with [`ask`] from any clone go into
+/// one queue, and each call to [`get_response`] sends the oldest queued message. The history stays
+/// locked while a request is in flight, so concurrent calls to [`get_response`] or [`send`] are sent
+/// one after another, each seeing the replies to the ones before it. Which task goes first is not
+/// specified.
+///
+/// [`send`]: #method.send
+#[derive(Clone)]
 pub struct Chat {
-    system: ChatMessage,
+    inner: Arc<ChatInner>,
+}
+
+/// The state shared by clones of a [`Chat`].
+struct ChatInner {
+    system: Mutex<ChatMessage>,
     chat_parameters: ChatParameters,
     api_key: String,
+    config: crate::RequestConfig,
     model: crate::ChatModel,
     len: usize,
+    max_history_messages: Option<usize>,
+    max_context_tokens: Option<usize>,
+    fit_context_window: bool,
+    stream_usage: bool,
+    usage: Mutex<Usage>,
     messages: Mutex<VecDeque<ChatMessage>>,
     message_queue: Mutex<VecDeque<ChatMessage>>,
 }
 
 impl Chat {
-    fn new<T: ToString>(
-        system: ChatMessage,
-        model: crate::ChatModel,
-        len: usize,
-        api_key: T,
-        chat_parameters: ChatParameters,
-    ) -> Self {
-        Self {
-            system,
-            chat_parameters,
-            api_key: api_key.to_string(),
-            model,
-            len: len * 2 + 2,
+    fn new(builder: ChatBuilder) -> Self {
+        let inner = ChatInner {
+            system: Mutex::new(builder.system),
+            chat_parameters: builder.chat_parameters,
+            api_key: builder.api_key,
+            config: builder.config,
+            model: builder.model,
+            // there is always room for the message being sent
+            len: builder.len.max(1),
+            max_history_messages: builder.max_history_messages.map(|max| max.max(1)),
+            max_context_tokens: builder.max_context_tokens,
+            fit_context_window: builder.fit_context_window,
+            stream_usage: builder.stream_usage,
+            usage: Mutex::new(Usage::default()),
             messages: Mutex::new(VecDeque::new()),
             message_queue: Mutex::new(VecDeque::new()),
+        };
+
+        Self {
+            inner: Arc::new(inner),
         }
     }
 
+    /// Clears the message history and any queued messages, starting a new conversation.
+    ///
+    /// The system message and the token usage so far are kept.
+    pub async fn reset(&self) {
+        self.inner.message_queue.lock().await.clear();
+        self.inner.messages.lock().await.clear();
+    }
+
+    /// Removes the most recent user message from the history along with the replies to it.
+    ///
+    /// Returns `false` if there was no user message to remove.
+    pub async fn pop_last(&self) -> bool {
+        let mut messages = self.inner.messages.lock().await;
+
+        match messages
+            .iter()
+            .rposition(|msg| matches!(msg.role, Role::User))
+        {
+            Some(index) => {
+                messages.truncate(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the tokens used by all responses in this chat session so far.
+    pub async fn total_usage(&self) -> Usage {
+        self.inner.usage.lock().await.clone()
+    }
+
     /// Get the messages that have been sent and received including the system and assistan messages.
     pub async fn get_messages(&self) -> Vec<ChatMessage> {
-        let mut messages = self.messages.lock().await.clone();
+        self.with_messages(|messages| messages.cloned().collect())
+            .await
+    }
 
-        messages.push_front(self.system.clone());
+    /// Calls `f` with an iterator over the message history (starting with the system message) without cloning it.
+    ///
+    /// The history stays locked while `f` runs, so [`get_response`] will wait for it to return.
+    ///
+    /// [`get_response`]: #method.get_response
+    pub async fn with_messages<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(Messages<'_>) -> R,
+    {
+        let system = self.inner.system.lock().await;
+        let messages = self.inner.messages.lock().await;
 
-        messages.into()
+        f(std::iter::once(&*system).chain(messages.iter()))
+    }
+
+    /// Replaces the system message, which takes effect from the next request on.
+    ///
+    /// The message history is kept.
+    pub async fn set_system(&self, message: ChatMessage) {
+        *self.inner.system.lock().await = message;
     }
 
     /// Adds a message to the queue to be sent to the API.
     pub async fn ask(&self, message: &str) -> Result<(), Box<dyn Error>> {
         let msg = ChatMessage {
             role: Role::User,
-            content: Some(message.to_string()),
+            content: Some(message.into()),
+            ..Default::default()
         };
 
-        self.message_queue.lock().await.push_back(msg);
+        self.inner.message_queue.lock().await.push_back(msg);
         Ok(())
     }
 
+    /// Adds the result of a tool call requested by the model to the message history.
+    ///
+    /// The next call to [`get_response`] sends it back to the model, even if nothing new was asked.
+    ///
+    /// [`get_response`]: #method.get_response
+    pub async fn submit_tool_result(&self, tool_call_id: String, content: String) {
+        let msg = ChatMessage {
+            role: Role::Tool,
+            content: Some(content.into()),
+            tool_call_id: Some(tool_call_id),
+            ..Default::default()
+        };
+
+        self.inner.messages.lock().await.push_back(msg);
+    }
+
     /// Sends the message history to the API including the last question asked, and returns the response.
+    ///
+    /// `user` overrides the default user set with [`ChatBuilder::user`] for this request only.
+    ///
+    /// [`ChatBuilder::user`]: ./struct.ChatBuilder.html#method.user
     pub async fn get_response(&self, user: Option<String>) -> Result<ChatMessage, Box<dyn Error>> {
+        self.respond(None, user, None).await.map(first_message)
+    }
+
+    /// Same as [`get_response`], but returns the whole response instead of just the message,
+    /// e.g. to log its id and usage or to check whether the reply was cut off by `max_tokens`.
+    ///
+    /// The first choice is added to the history, and the response is guaranteed to have one.
+    ///
+    /// [`get_response`]: #method.get_response
+    pub async fn get_full_response(
+        &self,
+        user: Option<String>,
+    ) -> Result<ChatResponse, Box<dyn Error>> {
+        self.respond(None, user, None).await
+    }
+
+    /// Same as [`get_response`], but returns every reply generated when [`ChatBuilder::n`] is set.
+    ///
+    /// Only the first reply is added to the history.
+    ///
+    /// [`get_response`]: #method.get_response
+    /// [`ChatBuilder::n`]: ./struct.ChatBuilder.html#method.n
+    pub async fn get_responses(
+        &self,
+        user: Option<String>,
+    ) -> Result<Vec<ChatMessage>, Box<dyn Error>> {
+        let resp = self.respond(None, user, None).await?;
+
+        Ok(resp
+            .choices
+            .into_iter()
+            .map(|choice| choice.message)
+            .collect())
+    }
+
+    /// Sends `message` and returns the response in one go, instead of calling [`ask`] and then [`get_response`].
+    ///
+    /// The message skips the queue, so messages added with [`ask`] are left for later calls to [`get_response`].
+    ///
+    /// [`ask`]: #method.ask
+    /// [`get_response`]: #method.get_response
+    pub async fn send(
+        &self,
+        message: &str,
+        user: Option<String>,
+    ) -> Result<ChatMessage, Box<dyn Error>> {
+        let message = ChatMessage::user(message);
+        self.respond(Some(message), user, None)
+            .await
+            .map(first_message)
+    }
+
+    /// Same as [`get_response`], but attaches `metadata` to this turn.
+    ///
+    /// The entries are merged into the default metadata set with [`ChatBuilder::metadata`],
+    /// replacing any default with the same key.
+    ///
+    /// [`get_response`]: #method.get_response
+    /// [`ChatBuilder::metadata`]: ./struct.ChatBuilder.html#method.metadata
+    pub async fn get_response_with_metadata(
+        &self,
+        user: Option<String>,
+        metadata: HashMap<String, String>,
+    ) -> Result<ChatMessage, Box<dyn Error>> {
+        self.respond(None, user, Some(metadata))
+            .await
+            .map(first_message)
+    }
+
+    /// Streams the response to the last question asked, forwarding each piece of content to `tx` as it arrives.
+    ///
+    /// Once the response is complete it is added to the history and returned, just like with [`get_response`].
+    /// If `tx`'s receiver is dropped before then, the request is aborted and the history is left as it was.
+    ///
+    /// [`get_response`]: #method.get_response
+    pub async fn stream_response(
+        &self,
+        tx: tokio::sync::mpsc::Sender<String>,
+    ) -> Result<ChatMessage, Box<dyn Error>> {
+        self.stream_full_response(tx)
+            .await
+            .map(|streamed| streamed.message)
+    }
+
+    /// Same as [`stream_response`], but also returns why the model stopped and the token usage,
+    /// which the stream only includes when it is asked for.
+    ///
+    /// [`stream_response`]: #method.stream_response
+    pub async fn stream_full_response(
+        &self,
+        tx: tokio::sync::mpsc::Sender<String>,
+    ) -> Result<StreamedResponse, Box<dyn Error>> {
+        let (mut messages, pushed, builder) = self.prepare(None, None, None).await?;
+
+        let builder = builder.stream(true).stream_usage(self.inner.stream_usage);
+
+        let mut stream = match builder.build_chat().send_stream().await {
+            Ok(stream) => stream,
+            Err(e) => {
+                if pushed {
+                    messages.pop_back();
+                }
+                return Err(e.into());
+            }
+        };
+
+        let mut role = None;
+        let mut content: Option<String> = None;
+        let mut tool_calls: BTreeMap<u32, ToolCallDelta> = BTreeMap::new();
+        let mut finish_reason = FinishReason::Null;
+        let mut usage = None;
+
+        while let Some(chunk) = stream.next().await {
+            let delta = match chunk {
+                Ok(ChatStreamChunk::Delta(chunk)) => {
+                    match chunk.choices.into_iter().find(|choice| choice.index == 0) {
+                        Some(choice) => {
+                            if choice.finish_reason.is_some() {
+                                finish_reason = choice.finish_reason.into();
+                            }
+                            choice.delta
+                        }
+                        None => continue,
+                    }
+                }
+                Ok(ChatStreamChunk::Usage(chunk)) => {
+                    usage = Some(chunk);
+                    continue;
+                }
+                Err(e) => {
+                    if pushed {
+                        messages.pop_back();
+                    }
+                    return Err(e.into());
+                }
+            };
+
+            role = role.or(delta.role);
+            for piece in delta.tool_calls.into_iter().flatten() {
+                let call = tool_calls.entry(piece.index).or_default();
+                call.id = call.id.take().or(piece.id);
+                call.tool_type = call.tool_type.take().or(piece.tool_type);
+                if let Some(function) = piece.function {
+                    let whole = call.function.get_or_insert_with(Default::default);
+                    whole.name = whole.name.take().or(function.name);
+                    if let Some(arguments) = function.arguments {
+                        whole
+                            .arguments
+                            .get_or_insert_with(String::new)
+                            .push_str(&arguments);
+                    }
+                }
+            }
+            let Some(delta) = delta.content else {
+                continue;
+            };
+            content.get_or_insert_with(String::new).push_str(&delta);
+
+            if delta.is_empty() {
+                continue;
+            }
+            if tx.send(delta).await.is_err() {
+                if pushed {
+                    messages.pop_back();
+                }
+                return Err("The receiver was dropped before the response was complete".into());
+            }
+        }
+
+        if role.is_none() && content.is_none() && tool_calls.is_empty() {
+            if pushed {
+                messages.pop_back();
+            }
+            return Err("Response contained no choices".into());
+        }
+
+        let tool_calls = match tool_calls
+            .into_values()
+            .map(assemble_tool_call)
+            .collect::<Option<Vec<_>>>()
+        {
+            Some(tool_calls) => tool_calls,
+            None => {
+                if pushed {
+                    messages.pop_back();
+                }
+                return Err("A streamed tool call was missing its id or function name".into());
+            }
+        };
+
+        let message = ChatMessage {
+            role: role.unwrap_or(Role::Assistant),
+            content: content.map(Content::Text),
+            tool_calls: Some(tool_calls).filter(|calls| !calls.is_empty()),
+            ..Default::default()
+        };
+        messages.push_back(message.clone());
+        self.trim_history(&mut messages);
+
+        if let Some(usage) = &usage {
+            self.add_usage(usage).await;
+        }
+
+        Ok(StreamedResponse {
+            message,
+            finish_reason,
+            usage,
+        })
+    }
+
+    /// Sends `message`, or the next queued message if there is none.
+    ///
+    /// The first choice of the response is added to the history, and the response is guaranteed to have one.
+    async fn respond(
+        &self,
+        message: Option<ChatMessage>,
+        user: Option<String>,
+        metadata: Option<HashMap<String, String>>,
+    ) -> Result<ChatResponse, Box<dyn Error>> {
+        let (mut messages, pushed, builder) = self.prepare(message, user, metadata).await?;
+        let req = builder.build_chat();
+
+        let resp = match req.send().await {
+            Ok(resp) => {
+                self.add_usage(&resp.usage).await;
+                resp
+            }
+            Err(e) => {
+                if pushed {
+                    messages.pop_back(); // remove the message we just added
+                }
+                return Err(e.into());
+            }
+        };
+
+        // e.g. content filtered responses come back without any choices
+        let Some(message) = resp.first_message() else {
+            if pushed {
+                messages.pop_back();
+            }
+            return Err("Response contained no choices".into());
+        };
+
+        messages.push_back(message.clone());
+        self.trim_history(&mut messages);
+
+        Ok(resp)
+    }
+
+    /// Adds `message` (or the next queued message) to the history and builds the request for it.
+    ///
+    /// Returns the locked history, whether a message was added to it, and the request builder.
+    /// The history stays locked until the response has been added to it.
+    async fn prepare(
+        &self,
+        message: Option<ChatMessage>,
+        user: Option<String>,
+        metadata: Option<HashMap<String, String>>,
+    ) -> Result<
+        (
+            MutexGuard<'_, VecDeque<ChatMessage>>,
+            bool,
+            crate::RequestBuilder<crate::ChatState>,
+        ),
+        Box<dyn Error>,
+    > {
         // the pushing and popping is in reverse order because we want to order the messages
         // in the API from oldest to newest.
 
-        let msg = if let Some(message) = self.message_queue.lock().await.pop_front() {
-            message
-        } else {
-            return Err("No message to send".into());
+        // cloned before locking the history, which `with_messages` locks after the system message
+        let system = self.inner.system.lock().await.clone();
+
+        let mut messages = self.inner.messages.lock().await;
+
+        // popped only while holding the history, so queued messages are sent in the order they were asked
+        let queued = match message {
+            Some(message) => Some(message),
+            None => self.inner.message_queue.lock().await.pop_front(),
         };
 
-        let mut messages = self.messages.lock().await;
+        let pushed = match queued {
+            Some(msg) => {
+                while user_messages(&messages) >= self.inner.len {
+                    pop_oldest_exchange(&mut messages);
+                }
 
-        if messages.len() >= self.len {
-            messages.pop_front();
-            messages.pop_front();
-            // pop the oldest user + assistant message
-        }
+                messages.push_back(msg);
+                true
+            }
+            // a submitted tool result can be answered without asking anything new
+            None if matches!(messages.back(), Some(msg) if matches!(msg.role, Role::Tool)) => false,
+            None => return Err("No message to send".into()),
+        };
+
+        let window = self.inner.fit_context_window.then(|| {
+            let reserved = self
+                .inner
+                .chat_parameters
+                .max_tokens
+                .map_or(DEFAULT_REPLY_TOKENS, |max_tokens| max_tokens as usize);
+            self.inner.model.context_window().saturating_sub(reserved)
+        });
 
-        messages.push_back(msg.clone());
+        if let Some(budget) = self
+            .inner
+            .max_context_tokens
+            .into_iter()
+            .chain(window)
+            .min()
+        {
+            let model = self.inner.model.to_string();
+            let tokens = |messages: &VecDeque<ChatMessage>| -> usize {
+                crate::tokens::count_messages(&model, std::iter::once(&system).chain(messages))
+            };
+
+            while tokens(&messages) > budget && user_messages(&messages) > 1 {
+                pop_oldest_exchange(&mut messages);
+            }
+        }
 
         let mut to_send = messages.clone();
-        to_send.push_front(self.system.clone());
+        self.trim_history(&mut to_send);
+        to_send.push_front(system);
 
-        let builder = crate::RequestBuilder::new(self.model.clone(), self.api_key.clone())
-            .messages(to_send.into())
-            .chat_parameters(self.chat_parameters.clone());
+        let mut chat_parameters = self.inner.chat_parameters.clone();
+
+        if let Some(metadata) = metadata {
+            chat_parameters
+                .metadata
+                .get_or_insert_with(HashMap::new)
+                .extend(metadata);
+        }
+
+        let builder =
+            crate::RequestBuilder::new(self.inner.model.clone(), self.inner.api_key.clone())
+                .config(self.inner.config.clone())
+                .messages(to_send)
+                .chat_parameters(chat_parameters);
 
         let builder = if let Some(user) = user {
             builder.user(user)
@@ -312,20 +1621,56 @@ impl Chat {
             builder
         };
 
-        let req = builder.build_chat();
+        Ok((messages, pushed, builder))
+    }
 
-        let resp = match req.send().await {
-            Ok(resp) => resp,
-            Err(e) => {
-                messages.pop_back(); // remove the message we just added
-                return Err(e.into());
-            }
+    /// Adds the tokens used by a response to [`total_usage`](#method.total_usage).
+    async fn add_usage(&self, used: &Usage) {
+        let mut usage = self.inner.usage.lock().await;
+        usage.prompt_tokens = usage.prompt_tokens.saturating_add(used.prompt_tokens);
+        usage.completion_tokens = usage
+            .completion_tokens
+            .saturating_add(used.completion_tokens);
+        usage.total_tokens = usage.total_tokens.saturating_add(used.total_tokens);
+    }
+
+    /// Drops the oldest messages until at most [`ChatBuilder::max_history_messages`] are left.
+    fn trim_history(&self, messages: &mut VecDeque<ChatMessage>) {
+        let Some(max) = self.inner.max_history_messages else {
+            return;
         };
 
-        let message = resp.choices[0].message.clone();
+        while messages.len() > max {
+            // the API rejects tool results whose call is missing, so they're dropped together
+            let oldest = 1 + messages
+                .iter()
+                .skip(1)
+                .take_while(|msg| matches!(msg.role, Role::Tool))
+                .count();
+            if oldest == messages.len() {
+                break;
+            }
+            messages.drain(..oldest);
+        }
+    }
+}
 
-        messages.push_back(message.clone());
+/// Takes the first message out of a response returned by [`Chat::respond`], which always has one.
+fn first_message(resp: ChatResponse) -> ChatMessage {
+    resp.choices.into_iter().next().unwrap().message
+}
+
+fn user_messages(messages: &VecDeque<ChatMessage>) -> usize {
+    messages
+        .iter()
+        .filter(|msg| matches!(msg.role, Role::User))
+        .count()
+}
 
-        Ok(message)
+/// Removes the oldest user message along with the replies to it.
+fn pop_oldest_exchange(messages: &mut VecDeque<ChatMessage>) {
+    messages.pop_front();
+    while matches!(messages.front(), Some(msg) if !matches!(msg.role, Role::User)) {
+        messages.pop_front();
     }
 }
@@ -2,25 +2,88 @@
 //!
 //! Includes the structs that represent a response from the Completion API.
 
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
 use serde::{ser::SerializeStruct, Deserialize, Serialize};
 
+use crate::chat::{FinishReason, Usage};
+
 #[derive(Debug, Serialize, Deserialize)]
 /// Represents one of the choices returned by the completion API.
 pub struct CompletionChoice {
     pub text: String,
+    /// The position of the choice. When sending several prompts this is `prompt * n + choice`,
+    /// so choices can be matched back to the prompt they answer.
+    #[serde(default)]
+    pub index: u32,
+    pub logprobs: Option<LogProbs>,
+    #[serde(default)]
+    pub finish_reason: FinishReason,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+/// The log probabilities of the tokens in a choice, returned when the `logprobs` parameter is set.
+///
+/// Each field has one entry per token. The first entries may be `null` when the prompt is echoed.
+pub struct LogProbs {
+    pub tokens: Vec<String>,
+    pub token_logprobs: Vec<Option<f64>>,
+    pub top_logprobs: Vec<Option<HashMap<String, f64>>>,
+    pub text_offset: Vec<u32>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+/// Represents one of the chunks of a streamed completion response.
+pub struct CompletionStreamChunk {
+    pub id: String,
+    #[serde(default)]
+    pub object: String,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<CompletionStreamChoice>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+/// Represents one of the choices in a streamed completion response.
+pub struct CompletionStreamChoice {
+    pub text: String,
+    #[serde(default)]
     pub index: u32,
-    pub logprobs: Option<u64>,
-    pub finish_reason: String,
+    pub finish_reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 /// Represents a response from the completion API.
 pub struct CompletionResponse {
     pub id: String,
+    #[serde(default)]
     pub object: String,
     pub created: u64,
     pub model: String,
     pub choices: Vec<CompletionChoice>,
+    pub usage: Usage,
+}
+
+impl CompletionResponse {
+    /// Returns the text of the first choice, or `None` if the response has no choices.
+    pub fn first_text(&self) -> Option<&str> {
+        self.choices.first().map(|choice| choice.text.as_str())
+    }
+
+    /// Returns the text of every choice, e.g. to pick between the candidates generated with `n`.
+    pub fn texts(&self) -> Vec<&str> {
+        self.choices
+            .iter()
+            .map(|choice| choice.text.as_str())
+            .collect()
+    }
+}
+
+impl Display for CompletionResponse {
+    /// Writes the text of the first choice, or nothing if there are no choices.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.first_text().unwrap_or_default())
+    }
 }
 
 impl Serialize for CompletionResponse {
@@ -28,12 +91,29 @@ impl Serialize for CompletionResponse {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("CompletionResponse", 5)?;
+        let mut state = serializer.serialize_struct("CompletionResponse", 6)?;
         state.serialize_field("id", &self.id)?;
         state.serialize_field("object", &self.object)?;
         state.serialize_field("created", &self.created)?;
         state.serialize_field("model", &self.model)?;
         state.serialize_field("choices", &self.choices)?;
+        state.serialize_field("usage", &self.usage)?;
         state.end()
     }
 }
+
+impl TryFrom<serde_json::Value> for CompletionResponse {
+    type Error = serde_json::Error;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        Self::deserialize(value)
+    }
+}
+
+impl FromStr for CompletionResponse {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
@@ -0,0 +1,45 @@
+//! # Embeddings API
+//!
+//! Includes the structs that represent a response from the embeddings API, which turns text into
+//! vectors that can be compared for semantic search.
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chat::Usage;
+
+#[derive(Debug, Deserialize, Serialize)]
+/// Represents a response from the embeddings API.
+pub struct EmbeddingResponse {
+    #[serde(default)]
+    pub object: String,
+    pub data: Vec<Embedding>,
+    pub model: String,
+    /// The embeddings API only reports prompt tokens, so `completion_tokens` is always 0.
+    pub usage: Usage,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+/// Represents the embedding of one of the inputs.
+pub struct Embedding {
+    pub embedding: Vec<f32>,
+    /// The position of the input this is the embedding of.
+    #[serde(default)]
+    pub index: u32,
+}
+
+impl TryFrom<serde_json::Value> for EmbeddingResponse {
+    type Error = serde_json::Error;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        Self::deserialize(value)
+    }
+}
+
+impl FromStr for EmbeddingResponse {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
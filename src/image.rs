@@ -0,0 +1,62 @@
+//! # Images API
+//!
+//! Includes the parameters and structs that represent a response from the image generation API,
+//! which creates images from a text prompt with DALL·E.
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+/// The size of the generated images.
+pub enum Size {
+    #[serde(rename = "256x256")]
+    S256,
+    #[serde(rename = "512x512")]
+    S512,
+    #[serde(rename = "1024x1024")]
+    S1024,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// How the generated images are returned.
+pub enum ImageResponseFormat {
+    /// A link to the image, which expires an hour after it was generated. This is the default.
+    Url,
+    /// The image itself, base64 encoded.
+    B64Json,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+/// Represents a response from the image generation API.
+pub struct ImageResponse {
+    pub created: u64,
+    pub data: Vec<ImageData>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+/// Represents one of the generated images.
+///
+/// Only one of `url` and `b64_json` is set, depending on the requested [`ImageResponseFormat`].
+pub struct ImageData {
+    pub url: Option<String>,
+    pub b64_json: Option<String>,
+    /// The prompt the image was actually generated from, if the model rewrote it.
+    pub revised_prompt: Option<String>,
+}
+
+impl TryFrom<serde_json::Value> for ImageResponse {
+    type Error = serde_json::Error;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        Self::deserialize(value)
+    }
+}
+
+impl FromStr for ImageResponse {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
@@ -1,6 +1,6 @@
-//! # OpenAI Completion/Chat Rust API
-//! Provides a neat and rusty way of interacting with the OpenAI Completion/Chat API.
-//! You can find the documentation for the API [here](https://platform.openai.com/docs/api-reference/completions).
+//! # OpenAI Rust API
+//! Provides a neat and rusty way of interacting with the OpenAI API.
+//! You can find the documentation for the API [here](https://platform.openai.com/docs/api-reference).
 //! ## Example
 //! ```rust no_run
 //! use rust_gpt::RequestBuilder;
@@ -9,23 +9,38 @@
 //!
 //! #[tokio::main]
 //! async fn main() {
-//!     let req = RequestBuilder::new(CompletionModel::TextDavinci003, "YOUR_API_KEY")
+//!     let req = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "YOUR_API_KEY")
 //!         .prompt("Write a sonnet about a crab named Ferris in the style of Shakespeare.")
 //!         .build_completion();
 //!     let response = req.send().await.unwrap();
-//!     println!("My bot replied with: \"{:?}\"", response);
+//!     println!("My bot replied with: \"{response}\"");
 //! }
 //!```
 //!
 //! ## General Usage
 //! You will most likely just use the [`RequestBuilder`] to create a request. You can then use the [`SendRequest`] trait to send the request.
-//! Right now only the completion and chat endpoints are supported.
-//! These two endpoints require different parameters, so you will need to use the [`build_completion`] and [`build_chat`] methods respectively.  
+//! Each endpoint takes different parameters, so each has its own method to finish the request, and
+//! the builder only offers the parameters that apply to the endpoint being built:
+//!
+//! | Endpoint | Build with | Response types |
+//! |----------|------------|----------------|
+//! | Completions | [`build_completion`] | [`completion`] |
+//! | Chat | [`build_chat`] | [`chat`] |
+//! | Responses | [`build_responses`] | [`responses`] |
+//! | Embeddings | [`build_embedding`] | [`embedding`] |
+//! | Moderations | [`build_moderation`] | [`moderation`] |
+//! | Image generation | [`build_image`] | [`image`] |
+//! | Audio transcription | [`transcribe`] | [`audio`] |
 //!
 //! [`RequestBuilder`] can take any type that implements [`ToString`] as the model input and any type that implements [`Display`] as the API key.
 //!
 //! [`build_completion`]: ./struct.RequestBuilder.html#method.build_completion
 //! [`build_chat`]: ./struct.RequestBuilder.html#method.build_chat
+//! [`build_responses`]: ./struct.RequestBuilder.html#method.build_responses
+//! [`build_embedding`]: ./struct.RequestBuilder.html#method.build_embedding
+//! [`build_moderation`]: ./struct.RequestBuilder.html#method.build_moderation
+//! [`build_image`]: ./struct.RequestBuilder.html#method.build_image
+//! [`transcribe`]: ./struct.RequestBuilder.html#method.transcribe
 //!
 //! ## Completion
 //! The completion endpoint requires a [`prompt`] parameter. You can set this with the [`prompt`] method which takes any type that implements [`ToString`].
@@ -36,10 +51,35 @@
 //! The chat endpoint is a little more complicated. It requires a [`messages`] parameter which is a list of messages.
 //! These messages are represented by the [`ChatMessage`] struct. You can create a [`ChatMessage`] with the [`new`] method.
 //!
+//! ```rust
+//! use rust_gpt::chat::{ChatMessage, Role};
+//! use rust_gpt::{ChatModel, RequestBuilder};
+//!
+//! let req = RequestBuilder::new(ChatModel::Gpt35Turbo, "YOUR_API_KEY")
+//!     .messages(vec![
+//!         ChatMessage::new(Role::System, "You are a helpful assistant."),
+//!         ChatMessage::user("Write a haiku about Ferris the crab."),
+//!     ])
+//!     .build_chat();
+//! ```
+//!
 //! [`messages`]: ./struct.RequestBuilder.html#method.messages
-//! [`new`]: ./struct.ChatMessage.html#method.new
+//! [`ChatMessage`]: ./chat/struct.ChatMessage.html
+//! [`new`]: ./chat/struct.ChatMessage.html#method.new
 //!
+//! ## Features
+//! | Feature      | Default | Description |
+//! |--------------|---------|-------------|
+//! | `rustls-tls` | yes     | Uses [rustls](https://docs.rs/rustls) for TLS. It needs no system libraries, so it works for fully static (e.g. musl) builds. |
+//! | `native-tls` | no      | Uses the platform's TLS library instead (OpenSSL on Linux). |
+//! | `blocking`   | no      | Adds `send_blocking` to requests, for use without an async runtime. |
+//! | `tiktoken`   | no      | Counts tokens exactly with OpenAI's BPE tables, see [`tokens`]. |
+//! | `tracing`    | no      | Records requests, token usage and failures with [tracing](https://docs.rs/tracing). |
+//! | `cancellation` | no    | Adds `send_cancellable` to requests, which gives up when a [`CancellationToken`](https://docs.rs/tokio-util/latest/tokio_util/sync/struct.CancellationToken.html) is cancelled. |
 //!
+//! To use the platform's TLS library, disable the default features:
+//! `rust-gpt = { version = "*", default-features = false, features = ["native-tls"] }`.
+//! At least one of the TLS features has to be enabled to reach the OpenAI API.
 //!
 //! ## Additional Notes
 //! The API is still in development, so there may be some breaking changes in the future.  
@@ -49,30 +89,132 @@
 //!
 
 #![allow(dead_code)]
-use std::{error::Error, fmt::Display};
+use std::{
+    collections::HashMap, error::Error, fmt::Display, future::Future, path::PathBuf, str::FromStr,
+    sync::Arc, time::Duration,
+};
 
-use async_trait::async_trait;
+use futures_util::{Stream, StreamExt};
 use once_cell::sync::OnceCell;
-use serde::Deserialize;
 use serde_json::json;
 
+pub mod audio;
+#[cfg(feature = "blocking")]
+mod blocking;
 pub mod chat;
 pub mod completion;
+pub mod embedding;
+pub mod image;
+pub mod moderation;
+pub mod pricing;
+pub mod prompt;
+mod request_log;
+pub mod responses;
+mod stream;
+pub mod tokens;
+pub mod transport;
 
 static RQCLIENT: OnceCell<reqwest::Client> = OnceCell::new();
-static COMPLETION_URL: &str = "https://api.openai.com/v1/completions";
-static CHAT_URL: &str = "https://api.openai.com/v1/chat/completions";
+static BASE_URL: &str = "https://api.openai.com/v1";
+static USER_AGENT: &str = concat!("rust-gpt/", env!("CARGO_PKG_VERSION"));
+static COMPLETION_PATH: &str = "/completions";
+static CHAT_PATH: &str = "/chat/completions";
+static RESPONSES_PATH: &str = "/responses";
+static EMBEDDING_PATH: &str = "/embeddings";
+static MODERATION_PATH: &str = "/moderations";
+static IMAGE_PATH: &str = "/images/generations";
+static TRANSCRIPTION_PATH: &str = "/audio/transcriptions";
+static API_KEY_VAR: &str = "OPENAI_API_KEY";
 
 #[derive(Debug, Clone)]
+/// A response body that couldn't be parsed into the expected type.
 pub struct JsonParseError {
     json_string: String,
+    source: Option<Arc<serde_json::Error>>,
+}
+
+impl JsonParseError {
+    pub(crate) fn new<T: Into<String>>(json_string: T, source: Option<serde_json::Error>) -> Self {
+        Self {
+            json_string: json_string.into(),
+            source: source.map(Arc::new),
+        }
+    }
+
+    /// The body that couldn't be parsed.
+    pub fn json_string(&self) -> &str {
+        &self.json_string
+    }
+}
+
+/// An error returned by the OpenAI API, deserialized from the `error` object of the response body.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct OpenAiApiError {
+    pub message: String,
+    #[serde(rename = "type", default)]
+    pub error_type: String,
+    pub param: Option<String>,
+    pub code: Option<String>,
+}
+
+impl OpenAiApiError {
+    /// Parses an error response body, falling back to using the whole body as the message
+    /// if it isn't an OpenAI error object (e.g. an HTML page from a proxy).
+    pub fn from_body(body: &str) -> Self {
+        serde_json::from_str::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| Self::parse(&json))
+            .unwrap_or_else(|| Self::raw(body))
+    }
+
+    pub(crate) fn from_json(json: &serde_json::Value) -> Self {
+        Self::parse(json).unwrap_or_else(|| Self::raw(serde_json::to_string_pretty(json).unwrap()))
+    }
+
+    fn parse(json: &serde_json::Value) -> Option<Self> {
+        serde_json::from_value(json["error"].clone()).ok()
+    }
+
+    fn raw(message: impl ToString) -> Self {
+        Self {
+            message: message.to_string(),
+            error_type: String::new(),
+            param: None,
+            code: None,
+        }
+    }
+}
+
+impl Display for OpenAiApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(code) = &self.code {
+            write!(f, " ({})", code)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
 pub enum SendRequestError {
     ReqwestError(reqwest::Error),
-    OpenAiError(String),
+    OpenAiError(OpenAiApiError),
     JsonError(JsonParseError),
+    /// The response was valid JSON, but had no `choices` array.
+    MissingChoices(JsonParseError),
+    /// The request did not finish within the deadline given to
+    /// [`Request::send_with_timeout`](./struct.Request.html#method.send_with_timeout).
+    Timeout(Duration),
+    /// The token given to `Request::send_cancellable` was cancelled before the request finished.
+    Cancelled,
+    /// The request was sent with `send` while streaming is enabled, or with `send_stream` while it
+    /// isn't. `streaming` is whether the request has streaming enabled. Nothing was sent.
+    StreamingMismatch {
+        streaming: bool,
+    },
+    /// The request was configured with something this way of sending it can't use, e.g. a
+    /// [`Transport`](transport::Transport) with `send_blocking`. Nothing was sent.
+    UnsupportedConfig(&'static str),
 }
 
 impl Display for SendRequestError {
@@ -81,6 +223,20 @@ impl Display for SendRequestError {
             SendRequestError::ReqwestError(e) => write!(f, "Reqwest error: {}", e),
             SendRequestError::OpenAiError(e) => write!(f, "OpenAI error: {}", e),
             SendRequestError::JsonError(e) => write!(f, "Json error: {}", e),
+            SendRequestError::MissingChoices(e) => {
+                write!(f, "Response has no `choices` array: {}", e.json_string)
+            }
+            SendRequestError::Timeout(d) => write!(f, "Request timed out after {:?}", d),
+            SendRequestError::Cancelled => write!(f, "Request was cancelled"),
+            SendRequestError::StreamingMismatch { streaming: true } => write!(
+                f,
+                "Streaming is enabled on this request, use send_stream or disable it with stream(false)."
+            ),
+            SendRequestError::UnsupportedConfig(reason) => write!(f, "{reason}"),
+            SendRequestError::StreamingMismatch { streaming: false } => write!(
+                f,
+                "Streaming is not enabled on this request, enable it with stream(true) or use send."
+            ),
         }
     }
 }
@@ -91,7 +247,156 @@ impl Display for JsonParseError {
     }
 }
 
-impl Error for SendRequestError {}
+impl Error for JsonParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_deref().map(|e| e as _)
+    }
+}
+
+impl Error for SendRequestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SendRequestError::ReqwestError(e) => Some(e),
+            SendRequestError::JsonError(e) => Some(e),
+            SendRequestError::MissingChoices(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl SendRequestError {
+    /// Returns `true` if OpenAI rejected the request because the account is out of credits
+    /// (`error.code == "insufficient_quota"`).
+    ///
+    /// These errors are returned with the same 429 status as rate limits, but unlike rate limits
+    /// retrying them will never succeed, so they should be treated as fatal.
+    pub fn is_insufficient_quota(&self) -> bool {
+        match self {
+            SendRequestError::OpenAiError(e) => e.code.as_deref() == Some("insufficient_quota"),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// An error returned when a request fails validation while it is being built.
+pub enum BuildError {
+    /// `stream_options` was set without enabling `stream`.
+    StreamOptionsWithoutStream,
+    /// `tool_choice` was set without providing any `tools`.
+    ToolChoiceWithoutTools,
+    /// `best_of` was set lower than `n`.
+    BestOfLessThanN { best_of: u64, n: u64 },
+    /// A `json_schema` response format was requested for a model that does not support it.
+    JsonSchemaUnsupported(String),
+    /// The model is known not to work with the chat endpoint.
+    NotAChatModel(String),
+    /// A single message is estimated to be longer than the model's context window.
+    MessageTooLong {
+        index: usize,
+        tokens: usize,
+        model: String,
+        context_window: usize,
+    },
+    /// More than [`MAX_STOP_SEQUENCES`] stop sequences were given.
+    TooManyStopSequences(usize),
+    /// `logprobs` was set higher than the API allows.
+    LogprobsTooHigh(u8),
+    /// The `OPENAI_API_KEY` environment variable is missing or empty.
+    MissingApiKey,
+    /// A sampling parameter is outside of the range the API accepts.
+    OutOfRange {
+        parameter: &'static str,
+        value: f64,
+        min: f64,
+        max: f64,
+    },
+    /// A `logit_bias` value is outside of `-100..=100`.
+    LogitBiasOutOfRange { token: u32, bias: f32 },
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::StreamOptionsWithoutStream => {
+                write!(f, "stream_options requires stream to be enabled")
+            }
+            BuildError::ToolChoiceWithoutTools => write!(f, "tool_choice requires tools to be set"),
+            BuildError::BestOfLessThanN { best_of, n } => {
+                write!(
+                    f,
+                    "best_of ({best_of}) must be greater than or equal to n ({n})"
+                )
+            }
+            BuildError::JsonSchemaUnsupported(model) => {
+                write!(
+                    f,
+                    "{model} does not support the json_schema response format"
+                )
+            }
+            BuildError::NotAChatModel(model) => write!(f, "{model} is not a chat model"),
+            BuildError::MessageTooLong {
+                index,
+                tokens,
+                model,
+                context_window,
+            } => write!(
+                f,
+                "message {index} is too long for {model} (~{tokens} tokens, the context window is {context_window})"
+            ),
+            BuildError::TooManyStopSequences(count) => write!(
+                f,
+                "{count} stop sequences were given, but at most {MAX_STOP_SEQUENCES} are allowed"
+            ),
+            BuildError::OutOfRange {
+                parameter,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "{parameter} is {value}, but it must be between {min} and {max}"
+            ),
+            BuildError::LogitBiasOutOfRange { token, bias } => write!(
+                f,
+                "the logit_bias for token {token} is {bias}, but it must be between -100 and 100"
+            ),
+            BuildError::MissingApiKey => {
+                write!(f, "the {API_KEY_VAR} environment variable is not set")
+            }
+            BuildError::LogprobsTooHigh(logprobs) => write!(
+                f,
+                "logprobs was set to {logprobs}, but at most {MAX_LOGPROBS} are allowed"
+            ),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+#[derive(Debug, Clone, PartialEq)]
+/// An error returned when parsing a [`chat::Role`] or a model from a string that doesn't name a known one.
+pub struct ParseError {
+    kind: &'static str,
+    value: String,
+}
+
+impl ParseError {
+    pub(crate) fn new(kind: &'static str, value: &str) -> Self {
+        Self {
+            kind,
+            value: value.to_string(),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown {}: {:?}", self.kind, self.value)
+    }
+}
+
+impl Error for ParseError {}
 
 impl From<reqwest::Error> for SendRequestError {
     fn from(e: reqwest::Error) -> Self {
@@ -99,7 +404,133 @@ impl From<reqwest::Error> for SendRequestError {
     }
 }
 
-#[async_trait]
+/// The most stop sequences the API accepts in a single request.
+pub const MAX_STOP_SEQUENCES: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+/// The `stop` parameter, which the API accepts either as a single string or as an array.
+///
+/// The builders always send [`Stop::Multiple`], but both forms deserialize, so saved request
+/// configs load back no matter how `stop` was written.
+pub enum Stop {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Stop {
+    /// Returns the number of stop sequences.
+    pub fn len(&self) -> usize {
+        match self {
+            Stop::Single(_) => 1,
+            Stop::Multiple(stop) => stop.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the stop sequences, whichever form they were given in.
+    pub fn sequences(&self) -> Vec<&str> {
+        match self {
+            Stop::Single(stop) => vec![stop.as_str()],
+            Stop::Multiple(stop) => stop.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+impl From<String> for Stop {
+    fn from(stop: String) -> Self {
+        Stop::Single(stop)
+    }
+}
+
+impl From<&str> for Stop {
+    fn from(stop: &str) -> Self {
+        Stop::Single(stop.to_string())
+    }
+}
+
+impl From<Vec<String>> for Stop {
+    fn from(stop: Vec<String>) -> Self {
+        Stop::Multiple(stop)
+    }
+}
+
+/// Checks that the sampling parameters are within the ranges the API accepts.
+pub(crate) fn validate_ranges(
+    temperature: Option<f64>,
+    top_p: Option<f64>,
+    presence_penalty: Option<f64>,
+    frequency_penalty: Option<f64>,
+) -> Result<(), BuildError> {
+    let checks = [
+        ("temperature", temperature, 0.0, 2.0),
+        ("top_p", top_p, 0.0, 1.0),
+        ("presence_penalty", presence_penalty, -2.0, 2.0),
+        ("frequency_penalty", frequency_penalty, -2.0, 2.0),
+    ];
+
+    for (parameter, value, min, max) in checks {
+        if let Some(value) = value {
+            if !(min..=max).contains(&value) {
+                return Err(BuildError::OutOfRange {
+                    parameter,
+                    value,
+                    min,
+                    max,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the API key from the `OPENAI_API_KEY` environment variable.
+pub(crate) fn api_key_from_env() -> Result<String, BuildError> {
+    std::env::var(API_KEY_VAR)
+        .ok()
+        .filter(|key| !key.trim().is_empty())
+        .ok_or(BuildError::MissingApiKey)
+}
+
+/// The most log probabilities the completion API returns per token.
+const MAX_LOGPROBS: u8 = 5;
+
+/// Converts stop sequences into the array form sent to the API.
+///
+/// Both the completion and chat endpoints accept an array, so every code path that sets `stop`
+/// goes through here to keep the serialized shape identical.
+pub(crate) fn stop_sequences<I, T>(stop: I) -> Stop
+where
+    I: IntoIterator<Item = T>,
+    T: ToString,
+{
+    Stop::Multiple(stop.into_iter().map(|s| s.to_string()).collect())
+}
+
+/// Converts string-like items into a JSON array, for parameters that take one or several strings.
+fn string_array<I>(items: I) -> serde_json::Value
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    items
+        .into_iter()
+        .map(|item| serde_json::Value::from(item.as_ref()))
+        .collect()
+}
+
+/// Checks that no more than [`MAX_STOP_SEQUENCES`] stop sequences were given.
+pub(crate) fn validate_stop_sequences(count: usize) -> Result<(), BuildError> {
+    if count > MAX_STOP_SEQUENCES {
+        return Err(BuildError::TooManyStopSequences(count));
+    }
+    Ok(())
+}
+
 /// A trait for abstracting sending requests between APIs.
 pub trait SendRequest {
     /// The type of the response.
@@ -107,20 +538,56 @@ pub trait SendRequest {
     /// The type of the error.
     type Error;
     /// Sends the request, returning whether or not there was an error with the response.
-    async fn send(self) -> Result<Self::Response, Self::Error>;
+    fn send(self) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send;
 }
 #[doc(hidden)]
 pub trait CompletionLike {}
 #[doc(hidden)]
+pub trait InputLike {}
+#[doc(hidden)]
+pub trait BatchInputLike {}
+#[doc(hidden)]
+pub trait PromptLike {}
+#[doc(hidden)]
+pub trait CountLike {}
+#[doc(hidden)]
+pub trait SamplingLike {}
+#[doc(hidden)]
+#[derive(Debug, Clone)]
 pub struct CompletionState;
 #[doc(hidden)]
+#[derive(Debug, Clone)]
 pub struct ChatState;
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct ResponsesState;
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct EmbeddingState;
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct ModerationState;
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct ImageState;
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct AudioState;
 #[derive(Debug, Clone)]
 /// The current completion models.
+///
+/// The davinci models have been retired by OpenAI and now return errors.
+/// They are kept for one more release to ease migration; use [`CompletionModel::Gpt35TurboInstruct`] instead.
 pub enum CompletionModel {
+    #[deprecated(note = "text-davinci-003 has been retired by OpenAI, use Gpt35TurboInstruct")]
     TextDavinci003,
+    #[deprecated(note = "text-davinci-002 has been retired by OpenAI, use Gpt35TurboInstruct")]
     TextDavinci002,
+    #[deprecated(note = "code-davinci-002 has been retired by OpenAI, use Gpt35TurboInstruct")]
     CodeDavinci002,
+    Gpt35TurboInstruct,
+    /// Any other model, sent to the API exactly as given.
+    Custom(String),
 }
 #[derive(Debug, Clone)]
 /// The current chat models.
@@ -129,238 +596,1569 @@ pub enum ChatModel {
     Gpt35Turbo0301,
     Gpt35Turbo16k,
     Gpt4,
+    Gpt40314,
     Gpt4_32k,
+    Gpt4_32k0314,
+    /// Any other model, like a new snapshot, sent to the API exactly as given.
+    Custom(String),
+}
+
+#[derive(Debug, Clone)]
+/// The current embedding models.
+pub enum EmbeddingModel {
+    TextEmbeddingAda002,
+    /// Any other model, sent to the API exactly as given.
+    Custom(String),
+}
+
+#[derive(Debug, Clone)]
+/// The current moderation models.
+pub enum ModerationModel {
+    TextModerationLatest,
+    TextModerationStable,
+    /// Any other model, sent to the API exactly as given.
+    Custom(String),
+}
+
+#[derive(Debug, Clone)]
+/// The current image generation models.
+pub enum ImageModel {
+    DallE2,
+    DallE3,
+    /// Any other model, sent to the API exactly as given.
+    Custom(String),
+}
+
+#[derive(Debug, Clone)]
+/// The current transcription models.
+pub enum AudioModel {
+    Whisper1,
+    /// Any other model, sent to the API exactly as given.
+    Custom(String),
 }
 
 impl CompletionLike for CompletionState {}
 impl CompletionLike for ChatState {}
+impl InputLike for ResponsesState {}
+impl InputLike for EmbeddingState {}
+impl InputLike for ModerationState {}
+impl BatchInputLike for EmbeddingState {}
+impl BatchInputLike for ModerationState {}
+impl PromptLike for CompletionState {}
+impl PromptLike for ImageState {}
+impl PromptLike for AudioState {}
+impl CountLike for CompletionState {}
+impl CountLike for ChatState {}
+impl CountLike for ImageState {}
+impl SamplingLike for CompletionState {}
+impl SamplingLike for ChatState {}
+impl SamplingLike for ResponsesState {}
+impl SamplingLike for AudioState {}
 
-impl ToString for CompletionModel {
-    fn to_string(&self) -> String {
-        match self {
+#[allow(deprecated)]
+impl Display for CompletionModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
             CompletionModel::TextDavinci003 => "text-davinci-003",
             CompletionModel::TextDavinci002 => "text-davinci-002",
             CompletionModel::CodeDavinci002 => "code-davinci-002",
-        }
-        .to_string()
+            CompletionModel::Gpt35TurboInstruct => "gpt-3.5-turbo-instruct",
+            CompletionModel::Custom(model) => model,
+        })
     }
 }
 
-impl ToString for ChatModel {
-    fn to_string(&self) -> String {
-        match self {
+impl CompletionModel {
+    /// Returns the model's context window, the most tokens a prompt and its completion can use together.
+    ///
+    /// [`CompletionModel::Custom`] models that aren't known fall back to 4096.
+    pub fn context_window(&self) -> usize {
+        tokens::context_window(&self.to_string()).unwrap_or(tokens::FALLBACK_CONTEXT_WINDOW)
+    }
+
+    /// Returns how many tokens are left for the completion after a prompt of `prompt_tokens` tokens.
+    pub fn max_output_tokens(&self, prompt_tokens: usize) -> usize {
+        self.context_window().saturating_sub(prompt_tokens)
+    }
+}
+
+#[allow(deprecated)]
+impl FromStr for CompletionModel {
+    type Err = ParseError;
+
+    /// Parses one of the known models. Use [`CompletionModel::Custom`] for any other model.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            CompletionModel::TextDavinci003,
+            CompletionModel::TextDavinci002,
+            CompletionModel::CodeDavinci002,
+            CompletionModel::Gpt35TurboInstruct,
+        ]
+        .into_iter()
+        .find(|model| model.to_string() == s)
+        .ok_or_else(|| ParseError::new("completion model", s))
+    }
+}
+
+impl Display for EmbeddingModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            EmbeddingModel::TextEmbeddingAda002 => "text-embedding-ada-002",
+            EmbeddingModel::Custom(model) => model,
+        })
+    }
+}
+
+impl Display for ModerationModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ModerationModel::TextModerationLatest => "text-moderation-latest",
+            ModerationModel::TextModerationStable => "text-moderation-stable",
+            ModerationModel::Custom(model) => model,
+        })
+    }
+}
+
+impl Display for ImageModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ImageModel::DallE2 => "dall-e-2",
+            ImageModel::DallE3 => "dall-e-3",
+            ImageModel::Custom(model) => model,
+        })
+    }
+}
+
+impl Display for AudioModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AudioModel::Whisper1 => "whisper-1",
+            AudioModel::Custom(model) => model,
+        })
+    }
+}
+
+impl Display for ChatModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
             ChatModel::Gpt35Turbo => "gpt-3.5-turbo",
             ChatModel::Gpt35Turbo0301 => "gpt-3.5-turbo-0301",
             ChatModel::Gpt35Turbo16k => "gpt-3.5-turbo-16k",
             ChatModel::Gpt4 => "gpt-4",
+            ChatModel::Gpt40314 => "gpt-4-0314",
             ChatModel::Gpt4_32k => "gpt-4-32k",
-        }
-        .to_string()
+            ChatModel::Gpt4_32k0314 => "gpt-4-32k-0314",
+            ChatModel::Custom(model) => model,
+        })
     }
 }
 
-#[derive(Debug)]
+impl ChatModel {
+    /// Returns the model's context window, the most tokens a request and its reply can use together.
+    ///
+    /// [`ChatModel::Custom`] models that aren't known fall back to 4096, the smallest window of the chat models.
+    pub fn context_window(&self) -> usize {
+        tokens::context_window(&self.to_string()).unwrap_or(tokens::FALLBACK_CONTEXT_WINDOW)
+    }
+
+    /// Returns how many tokens are left for the reply after a prompt of `prompt_tokens` tokens.
+    pub fn max_output_tokens(&self, prompt_tokens: usize) -> usize {
+        self.context_window().saturating_sub(prompt_tokens)
+    }
+}
+
+impl FromStr for ChatModel {
+    type Err = ParseError;
+
+    /// Parses one of the known models. Use [`ChatModel::Custom`] for any other model.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            ChatModel::Gpt35Turbo,
+            ChatModel::Gpt35Turbo0301,
+            ChatModel::Gpt35Turbo16k,
+            ChatModel::Gpt4,
+            ChatModel::Gpt40314,
+            ChatModel::Gpt4_32k,
+            ChatModel::Gpt4_32k0314,
+        ]
+        .into_iter()
+        .find(|model| model.to_string() == s)
+        .ok_or_else(|| ParseError::new("chat model", s))
+    }
+}
+
+#[derive(Debug, Clone)]
 /// A generic request which can be used to send requests to the OpenAI API.
 pub struct Request<T> {
     to_send: String,
+    /// Uploaded along with the parameters, which are then sent as a form instead of JSON.
+    file: Option<audio::AudioFile>,
     api_key: String,
+    config: RequestConfig,
     state: std::marker::PhantomData<T>,
 }
 
-#[async_trait]
-impl SendRequest for Request<CompletionState> {
-    type Response = completion::CompletionResponse;
-    type Error = SendRequestError;
-    async fn send(self) -> Result<Self::Response, Self::Error> {
-        use SendRequestError::*;
-        let client = RQCLIENT.get_or_init(reqwest::Client::new);
-
-        let resp = client
-            .post(COMPLETION_URL)
-            .header("Content-Type", "application/json")
-            .header("Authorization", self.api_key)
-            .body(self.to_send)
-            .send()
-            .await?;
-
-        let body = resp.text().await.unwrap();
-        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
-
-        let response = match completion::CompletionResponse::deserialize(json.clone()) {
-            Ok(r) => r,
-            Err(_) => {
-                return Err(JsonError(JsonParseError {
-                    json_string: serde_json::to_string_pretty(&json).unwrap(),
-                }))
-            }
-        };
+#[derive(Debug, Clone)]
+/// Settings for how a request is sent, shared between [`RequestBuilder`] and the chat session builder.
+pub(crate) struct RequestConfig {
+    base_url: String,
+    user_agent: String,
+    organization: Option<String>,
+    headers: Vec<(String, String)>,
+    fresh_client: bool,
+    client: Option<reqwest::Client>,
+    timeout: Option<Duration>,
+    retry: Option<RetryConfig>,
+    log_file: Option<PathBuf>,
+    transport: Option<Arc<dyn transport::Transport>>,
+}
 
-        Ok(response)
+impl Default for RequestConfig {
+    fn default() -> Self {
+        Self {
+            base_url: BASE_URL.to_string(),
+            user_agent: USER_AGENT.to_string(),
+            organization: None,
+            headers: Vec::new(),
+            fresh_client: false,
+            client: None,
+            timeout: None,
+            retry: None,
+            log_file: None,
+            transport: None,
+        }
     }
 }
 
-#[async_trait]
-impl SendRequest for Request<ChatState> {
-    type Response = chat::ChatResponse;
-    type Error = SendRequestError;
+impl RequestConfig {
+    pub(crate) fn base_url<T: ToString>(&mut self, base_url: T) {
+        self.base_url = base_url.to_string().trim_end_matches('/').to_string();
+    }
 
-    async fn send(self) -> Result<Self::Response, SendRequestError> {
-        use SendRequestError::*;
+    pub(crate) fn user_agent<T: ToString>(&mut self, user_agent: T) {
+        self.user_agent = user_agent.to_string();
+    }
 
-        if !self.to_send.contains("messages") {
-            return Err(OpenAiError("No messages in request.".into()));
-        }
+    pub(crate) fn organization<T: Into<String>>(&mut self, organization: T) {
+        self.organization = Some(organization.into());
+    }
+
+    pub(crate) fn header<N: Into<String>, V: Into<String>>(&mut self, name: N, value: V) {
+        let name = name.into();
+
+        self.headers
+            .retain(|(header, _)| !header.eq_ignore_ascii_case(&name));
+        self.headers.push((name, value.into()));
+    }
+
+    pub(crate) fn client(&mut self, client: reqwest::Client) {
+        self.client = Some(client);
+    }
 
-        let client = RQCLIENT.get_or_init(reqwest::Client::new);
+    pub(crate) fn transport<T: transport::Transport + 'static>(&mut self, transport: T) {
+        self.transport = Some(Arc::new(transport));
+    }
+
+    pub(crate) fn timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    pub(crate) fn retry(&mut self, retry: RetryConfig) {
+        self.retry = Some(retry);
+    }
 
-        let resp = client
-            .post(CHAT_URL)
-            .header("Content-Type", "application/json")
-            .header("Authorization", self.api_key)
-            .body(self.to_send)
-            .send()
-            .await?;
+    pub(crate) fn log_to<P: Into<PathBuf>>(&mut self, path: P) {
+        self.log_file = Some(path.into());
+    }
+}
 
-        let body = resp.text().await.unwrap();
-        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+#[derive(Debug, Clone)]
+/// How often and how long to wait before retrying a request that was rate limited or hit a server error.
+///
+/// Requests are retried on `429`, `500`, `502` and `503` responses, except when the account is out of
+/// credits (see [`SendRequestError::is_insufficient_quota`]). The delay doubles after every attempt,
+/// starting at `base_delay`, unless the response has a `Retry-After` header, which is used instead.
+pub struct RetryConfig {
+    /// How many times to retry before returning the error.
+    pub max_retries: u32,
+    /// How long to wait before the first retry.
+    pub base_delay: Duration,
+}
 
-        if !json["error"].is_null() {
-            return Err(OpenAiError(
-                serde_json::to_string_pretty(&json).unwrap().into(),
-            ));
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_secs(1),
         }
+    }
+}
 
-        let response = match chat::ChatResponse::deserialize(json.clone()) {
-            Ok(r) => r,
-            Err(_) => {
-                return Err(JsonError(JsonParseError {
-                    json_string: serde_json::to_string_pretty(&json).unwrap(),
-                }))
-            }
-        };
+impl RetryConfig {
+    fn is_retryable(status: reqwest::StatusCode) -> bool {
+        matches!(status.as_u16(), 429 | 500 | 502 | 503)
+    }
 
-        Ok(response)
+    fn delay(&self, attempt: u32, headers: &reqwest::header::HeaderMap) -> Duration {
+        let retry_after = headers
+            .get("retry-after")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<f64>().ok())
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok());
 
-        // Ok(ChatResponse {
-        //     id: json["id"].as_str().unwrap().to_string(),
-        //     object: json["object"].as_str().unwrap().to_string(),
-        //     created: json["created"].as_u64().unwrap(),
-        //     model: json["model"].as_str().unwrap().to_string(),
-        //     usage: (
-        //         json["usage"]["prompt_tokens"].as_u64().unwrap() as u32,
-        //         json["usage"]["completion_tokens"].as_u64().unwrap() as u32,
-        //         json["usage"]["total_tokens"].as_u64().unwrap() as u32,
-        //     ),
-        //     choices: json["choices"].as_array().unwrap().iter().map(|message| ChatMessage {
-        //         role: message["message"]["role"].as_str().unwrap().try_into().unwrap(),
-        //         content: message["message"]["content"].as_str().unwrap().to_string(),
-        //     }).collect()
-        // })
+        retry_after.unwrap_or_else(|| self.base_delay.saturating_mul(2u32.saturating_pow(attempt)))
     }
 }
 
 #[derive(Debug)]
-/// A builder for creating requests to the OpenAI API.
-pub struct RequestBuilder<T> {
-    req: serde_json::Value,
-    api_key: String,
-    state: std::marker::PhantomData<T>,
+/// A response from the OpenAI API together with the HTTP details it came with.
+///
+/// Returned by the `send_detailed` methods on [`Request`].
+pub struct Response<T> {
+    /// The deserialized response body.
+    pub body: T,
+    /// The HTTP status code.
+    pub status: reqwest::StatusCode,
+    /// The `x-request-id` header, which OpenAI support asks for when debugging a request.
+    pub request_id: Option<String>,
+    /// The rate limit headers sent with the response.
+    pub rate_limit: RateLimit,
+    /// The response body exactly as it was received.
+    pub raw_body: String,
 }
 
-impl<C: CompletionLike> RequestBuilder<C> {
-    /// Create a new request builder.
-    pub fn new<T: ToString, S: Display>(model: T, api_key: S) -> Self {
-        let api_key = format!("Bearer {api_key}");
+#[derive(Debug, Clone, Default)]
+/// The `x-ratelimit-*` headers returned by the OpenAI API.
+pub struct RateLimit {
+    pub limit_requests: Option<u32>,
+    pub limit_tokens: Option<u32>,
+    pub remaining_requests: Option<u32>,
+    pub remaining_tokens: Option<u32>,
+    pub reset_requests: Option<String>,
+    pub reset_tokens: Option<String>,
+}
 
-        let req = json!({
-            "model": model.to_string(),
-        });
+impl RateLimit {
+    fn from_headers(headers: &reqwest::header::HeaderMap) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let number = |name: &str| header(name).and_then(|value| value.parse().ok());
 
         Self {
-            req,
-            api_key,
-            state: std::marker::PhantomData,
+            limit_requests: number("x-ratelimit-limit-requests"),
+            limit_tokens: number("x-ratelimit-limit-tokens"),
+            remaining_requests: number("x-ratelimit-remaining-requests"),
+            remaining_tokens: number("x-ratelimit-remaining-tokens"),
+            reset_requests: header("x-ratelimit-reset-requests"),
+            reset_tokens: header("x-ratelimit-reset-tokens"),
         }
     }
-    /// Set the max_tokens parameter.
-    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
-        self.req["max_tokens"] = json!(max_tokens);
-        self
-    }
-    /// Set the temperature parameter.
-    pub fn temperature(mut self, temperature: f32) -> Self {
-        self.req["temperature"] = json!(temperature);
-        self
+}
+
+/// Deserializes a response body, reporting an `error` object in it as [`SendRequestError::OpenAiError`].
+fn parse_json<R>(json: &serde_json::Value) -> Result<R, SendRequestError>
+where
+    R: TryFrom<serde_json::Value, Error = serde_json::Error>,
+{
+    if !json["error"].is_null() {
+        return Err(SendRequestError::OpenAiError(OpenAiApiError::from_json(
+            json,
+        )));
     }
-    /// Set the top_p parameter.
-    pub fn top_p(mut self, top_p: f32) -> Self {
-        self.req["top_p"] = json!(top_p);
-        self
+
+    R::try_from(json.clone()).map_err(|e| {
+        SendRequestError::JsonError(JsonParseError::new(
+            serde_json::to_string_pretty(json).unwrap(),
+            Some(e),
+        ))
+    })
+}
+
+/// Like [`parse_json`], but reports a body without the `choices` array that completions and chats
+/// always have as [`SendRequestError::MissingChoices`].
+fn parse_choices<R>(json: &serde_json::Value) -> Result<R, SendRequestError>
+where
+    R: TryFrom<serde_json::Value, Error = serde_json::Error>,
+{
+    if json["error"].is_null() && !json["choices"].is_array() {
+        return Err(SendRequestError::MissingChoices(JsonParseError::new(
+            serde_json::to_string_pretty(json).unwrap(),
+            None,
+        )));
     }
-    /// Set the frequency_penalty parameter.
-    pub fn frequency_penalty(mut self, frequency_penalty: f32) -> Self {
-        self.req["frequency_penalty"] = json!(frequency_penalty);
-        self
+
+    parse_json(json)
+}
+
+/// Turns the body of a non-success response into an error.
+fn error_from_response(status: reqwest::StatusCode, body: &str) -> SendRequestError {
+    // error pages from OpenAI or a proxy in between aren't necessarily JSON, so check before parsing
+    let error = serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|json| OpenAiApiError::parse(&json))
+        .unwrap_or_else(|| OpenAiApiError::raw(format!("{status}: {body}")));
+
+    SendRequestError::OpenAiError(error)
+}
+
+/// The parts of an HTTP response needed to build a [`Response`].
+struct RawResponse {
+    status: reqwest::StatusCode,
+    headers: reqwest::header::HeaderMap,
+    body: String,
+    log: Option<request_log::RequestLog>,
+}
+
+impl RawResponse {
+    fn json(&self) -> Result<serde_json::Value, SendRequestError> {
+        serde_json::from_str(&self.body).map_err(|e| {
+            SendRequestError::JsonError(JsonParseError::new(self.body.clone(), Some(e)))
+        })
+    }
+
+    /// Like [`log`](Self::log), but blocks the current thread.
+    #[cfg(feature = "blocking")]
+    fn log_blocking(&self, json: &serde_json::Value) {
+        Self::trace(json);
+
+        if let Some(log) = &self.log {
+            log.write_blocking(json);
+        }
+    }
+
+    /// Writes the request and this response to the log file, if one was set, and records it with `tracing`.
+    async fn log(&self, json: &serde_json::Value) {
+        Self::trace(json);
+
+        if let Some(log) = &self.log {
+            log.write(json).await;
+        }
+    }
+
+    /// Records the token usage of the response with `tracing`, if the feature is enabled.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn trace(json: &serde_json::Value) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(usage = %json["usage"], "request succeeded");
+    }
+
+    fn into_response<T>(self, body: T) -> Response<T> {
+        Response {
+            body,
+            status: self.status,
+            request_id: self
+                .headers
+                .get("x-request-id")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            rate_limit: RateLimit::from_headers(&self.headers),
+            raw_body: self.body,
+        }
+    }
+}
+
+impl<T> Request<T>
+where
+    Request<T>: SendRequest<Error = SendRequestError>,
+{
+    /// Sends the request, giving up with [`SendRequestError::Timeout`] if it hasn't finished within `timeout`.
+    ///
+    /// Unlike a timeout on the HTTP client, this bounds the whole call, including reading the response body.
+    pub async fn send_with_timeout(
+        self,
+        timeout: Duration,
+    ) -> Result<<Self as SendRequest>::Response, SendRequestError> {
+        tokio::time::timeout(timeout, self.send())
+            .await
+            .unwrap_or(Err(SendRequestError::Timeout(timeout)))
+    }
+
+    /// Sends the request, giving up with [`SendRequestError::Cancelled`] as soon as `token` is cancelled.
+    ///
+    /// The connection is dropped right away, so this also stops requests that are still waiting for the API.
+    #[cfg(feature = "cancellation")]
+    pub async fn send_cancellable(
+        self,
+        token: &tokio_util::sync::CancellationToken,
+    ) -> Result<<Self as SendRequest>::Response, SendRequestError> {
+        if token.is_cancelled() {
+            return Err(SendRequestError::Cancelled);
+        }
+
+        let send = std::pin::pin!(self.send());
+        let cancelled = std::pin::pin!(token.cancelled());
+
+        match futures_util::future::select(send, cancelled).await {
+            futures_util::future::Either::Left((result, _)) => result,
+            futures_util::future::Either::Right(_) => Err(SendRequestError::Cancelled),
+        }
+    }
+}
+
+impl<T> Request<T> {
+    /// Sends this request with a new HTTP client instead of the one shared by all requests.
+    ///
+    /// This is mostly useful in tests, where sharing pooled connections between requests
+    /// pointed at different mock servers can make them interfere with each other.
+    pub fn with_fresh_client(mut self) -> Self {
+        self.config.fresh_client = true;
+        self
+    }
+
+    /// The JSON body that will be posted when this request is sent.
+    ///
+    /// Requests that upload a file send these parameters as form fields instead.
+    pub fn body(&self) -> &str {
+        &self.to_send
+    }
+
+    /// Returns `true` if the request asks for a streamed response.
+    fn is_streaming(&self) -> bool {
+        serde_json::from_str::<serde_json::Value>(&self.to_send)
+            .map(|req| req["stream"] == true)
+            .unwrap_or(false)
+    }
+
+    /// Checks that the request doesn't ask for a stream, which would come back as events instead of JSON.
+    fn check_not_streaming(&self) -> Result<(), SendRequestError> {
+        if self.is_streaming() {
+            return Err(SendRequestError::StreamingMismatch { streaming: true });
+        }
+        Ok(())
+    }
+
+    async fn post(self, path: &str) -> Result<RawResponse, SendRequestError> {
+        self.check_not_streaming()?;
+
+        let log = self
+            .config
+            .log_file
+            .clone()
+            .map(|file| request_log::RequestLog::new(file, self.to_send.clone()));
+        let mut attempt = 0;
+
+        loop {
+            let (status, headers, body) = self.exchange(path).await?;
+
+            if status.is_success() {
+                return Ok(RawResponse {
+                    status,
+                    headers,
+                    body,
+                    log,
+                });
+            }
+
+            let error = error_from_response(status, &body);
+
+            match self.retry_delay(attempt, status, &headers, &error) {
+                Some(delay) => {
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                None => {
+                    self.trace_error(path, status, &body);
+                    return Err(error);
+                }
+            }
+        }
+    }
+
+    /// Posts the request to `path` and parses the JSON response with `parse`.
+    async fn send_json<R>(
+        self,
+        path: &str,
+        parse: fn(&serde_json::Value) -> Result<R, SendRequestError>,
+    ) -> Result<Response<R>, SendRequestError> {
+        let raw = self.post(path).await?;
+        let json = raw.json()?;
+        let response = parse(&json)?;
+
+        raw.log(&json).await;
+        Ok(raw.into_response(response))
+    }
+
+    /// Records the request with `tracing`, if the feature is enabled. The API key is never recorded.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn trace_request(&self, path: &str) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            url = %format!("{}{}", self.config.base_url, path),
+            model = %serde_json::from_str::<serde_json::Value>(&self.to_send)
+                .map(|req| req["model"].clone())
+                .unwrap_or_default(),
+            "sending request"
+        );
+    }
+
+    /// Records a failed request with `tracing`, if the feature is enabled.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn trace_error(&self, path: &str, status: reqwest::StatusCode, body: &str) {
+        #[cfg(feature = "tracing")]
+        tracing::error!(
+            url = %format!("{}{}", self.config.base_url, path),
+            %status,
+            body,
+            "request failed"
+        );
+    }
+
+    /// Returns how long to wait before retrying a failed attempt, or `None` if it shouldn't be retried.
+    fn retry_delay(
+        &self,
+        attempt: u32,
+        status: reqwest::StatusCode,
+        headers: &reqwest::header::HeaderMap,
+        error: &SendRequestError,
+    ) -> Option<Duration> {
+        let retry = self.config.retry.as_ref()?;
+
+        (attempt < retry.max_retries
+            && RetryConfig::is_retryable(status)
+            && !error.is_insufficient_quota())
+        .then(|| retry.delay(attempt, headers))
+    }
+
+    /// The headers sent with every request, followed by the ones set with [`RequestBuilder::header`].
+    fn headers(&self) -> Vec<(&str, &str)> {
+        let mut headers = vec![
+            ("User-Agent", self.config.user_agent.as_str()),
+            ("Authorization", self.api_key.as_str()),
+        ];
+
+        // forms get a content type with their boundary from reqwest
+        if self.file.is_none() {
+            headers.insert(0, ("Content-Type", "application/json"));
+        }
+
+        if let Some(organization) = &self.config.organization {
+            headers.push(("OpenAI-Organization", organization.as_str()));
+        }
+
+        for (name, value) in &self.config.headers {
+            headers.retain(|(header, _)| !header.eq_ignore_ascii_case(name));
+            headers.push((name.as_str(), value.as_str()));
+        }
+
+        headers
+    }
+
+    /// Sends the request once and returns the status, headers and body of the response.
+    ///
+    /// Responses from a [`Transport`](transport::Transport) have no headers.
+    /// Transports only carry text, so requests with a file fail instead of bypassing the transport.
+    async fn exchange(
+        &self,
+        path: &str,
+    ) -> Result<(reqwest::StatusCode, reqwest::header::HeaderMap, String), SendRequestError> {
+        if let Some(transport) = &self.config.transport {
+            if self.file.is_some() {
+                return Err(SendRequestError::UnsupportedConfig(
+                    "Transports only carry text and can't upload files, remove the transport to send this request.",
+                ));
+            }
+
+            self.trace_request(path);
+
+            let url = format!("{}{}", self.config.base_url, path);
+            let (status, body) = transport.post(&url, &self.headers(), &self.to_send).await?;
+
+            return Ok((status, reqwest::header::HeaderMap::new(), body));
+        }
+
+        let resp = self.send_raw(path).await?;
+
+        Ok((resp.status(), resp.headers().clone(), resp.text().await?))
+    }
+
+    /// Sends the request, checking that it asked for a streamed response.
+    async fn post_stream<E>(
+        self,
+        path: &str,
+    ) -> Result<impl Stream<Item = Result<E, SendRequestError>> + Send + Unpin, SendRequestError>
+    where
+        E: serde::de::DeserializeOwned + Send,
+    {
+        if !self.is_streaming() {
+            return Err(SendRequestError::StreamingMismatch { streaming: false });
+        }
+
+        if self.config.transport.is_some() {
+            // the whole body is read at once, so the events arrive together at the end
+            let (status, _, body) = self.exchange(path).await?;
+
+            if !status.is_success() {
+                self.trace_error(path, status, &body);
+
+                return Err(SendRequestError::OpenAiError(OpenAiApiError::from_body(
+                    &body,
+                )));
+            }
+
+            let bytes = futures_util::stream::iter([Ok::<_, reqwest::Error>(body)]);
+            return Ok(stream::events(bytes).left_stream());
+        }
+
+        let resp = self.send_raw(path).await?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await?;
+            self.trace_error(path, status, &body);
+
+            return Err(SendRequestError::OpenAiError(OpenAiApiError::from_body(
+                &body,
+            )));
+        }
+
+        Ok(stream::events(resp.bytes_stream()).right_stream())
+    }
+
+    async fn send_raw(&self, path: &str) -> Result<reqwest::Response, SendRequestError> {
+        self.trace_request(path);
+
+        let fresh_client;
+        let client = if let Some(client) = &self.config.client {
+            client
+        } else if self.config.fresh_client {
+            fresh_client = reqwest::Client::new();
+            &fresh_client
+        } else {
+            RQCLIENT.get_or_init(reqwest::Client::new)
+        };
+
+        let mut req = client.post(format!("{}{}", self.config.base_url, path));
+
+        for (name, value) in self.headers() {
+            req = req.header(name, value);
+        }
+
+        if let Some(timeout) = self.config.timeout {
+            req = req.timeout(timeout);
+        }
+
+        let req = match &self.file {
+            Some(file) => req.multipart(self.multipart(file)),
+            None => req.body(self.to_send.clone()),
+        };
+
+        Ok(req.send().await?)
+    }
+}
+
+impl Request<CompletionState> {
+    /// Sends the request and returns the response as a stream of chunks as they are generated.
+    ///
+    /// Streaming has to be enabled with [`RequestBuilder::stream`] first.
+    /// The stream ends when OpenAI sends its `[DONE]` message.
+    ///
+    /// [`RequestBuilder::stream`]: ./struct.RequestBuilder.html#method.stream
+    pub async fn send_stream(
+        self,
+    ) -> Result<
+        impl Stream<Item = Result<completion::CompletionStreamChunk, SendRequestError>> + Send + Unpin,
+        SendRequestError,
+    > {
+        self.post_stream(COMPLETION_PATH).await
+    }
+
+    /// Sends the request, returning the response along with its HTTP status, headers and raw body.
+    pub async fn send_detailed(
+        self,
+    ) -> Result<Response<completion::CompletionResponse>, SendRequestError> {
+        self.send_json(COMPLETION_PATH, parse_choices).await
+    }
+}
+
+impl Request<ChatState> {
+    /// Sends the request and returns the response as a stream of chunks as they are generated.
+    ///
+    /// Streaming has to be enabled with [`RequestBuilder::stream`] first.
+    /// The stream ends when OpenAI sends its `[DONE]` message.
+    ///
+    /// [`RequestBuilder::stream`]: ./struct.RequestBuilder.html#method.stream
+    pub async fn send_stream(
+        self,
+    ) -> Result<
+        impl Stream<Item = Result<chat::ChatStreamChunk, SendRequestError>> + Send + Unpin,
+        SendRequestError,
+    > {
+        self.check_messages()?;
+
+        self.post_stream(CHAT_PATH).await
+    }
+
+    /// Sends the request, returning the response along with its HTTP status, headers and raw body.
+    pub async fn send_detailed(self) -> Result<Response<chat::ChatResponse>, SendRequestError> {
+        self.check_messages()?;
+
+        self.send_json(CHAT_PATH, parse_choices).await
+    }
+
+    /// Checks that the request has a non-empty `messages` array, which the API requires.
+    fn check_messages(&self) -> Result<(), SendRequestError> {
+        let has_messages = serde_json::from_str::<serde_json::Value>(&self.to_send)
+            .ok()
+            .and_then(|req| {
+                req["messages"]
+                    .as_array()
+                    .map(|messages| !messages.is_empty())
+            })
+            .unwrap_or(false);
+
+        if !has_messages {
+            return Err(SendRequestError::OpenAiError(OpenAiApiError::raw(
+                "No messages in request.",
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Request<ResponsesState> {
+    /// Sends the request, returning the response along with its HTTP status, headers and raw body.
+    pub async fn send_detailed(
+        self,
+    ) -> Result<Response<responses::ResponsesResult>, SendRequestError> {
+        self.send_json(RESPONSES_PATH, parse_json).await
+    }
+}
+
+impl Request<EmbeddingState> {
+    /// Sends the request, returning the response along with its HTTP status, headers and raw body.
+    pub async fn send_detailed(
+        self,
+    ) -> Result<Response<embedding::EmbeddingResponse>, SendRequestError> {
+        self.send_json(EMBEDDING_PATH, parse_json).await
+    }
+}
+
+impl Request<ModerationState> {
+    /// Sends the request, returning the response along with its HTTP status, headers and raw body.
+    pub async fn send_detailed(
+        self,
+    ) -> Result<Response<moderation::ModerationResponse>, SendRequestError> {
+        self.send_json(MODERATION_PATH, parse_json).await
+    }
+}
+
+impl Request<ImageState> {
+    /// Sends the request, returning the response along with its HTTP status, headers and raw body.
+    pub async fn send_detailed(self) -> Result<Response<image::ImageResponse>, SendRequestError> {
+        self.send_json(IMAGE_PATH, parse_json).await
+    }
+}
+
+impl Request<AudioState> {
+    /// Sends the request, returning the response along with its HTTP status, headers and raw body.
+    pub async fn send_detailed(
+        self,
+    ) -> Result<Response<audio::TranscriptionResponse>, SendRequestError> {
+        self.send_json(TRANSCRIPTION_PATH, parse_json).await
+    }
+}
+
+impl SendRequest for Request<CompletionState> {
+    type Response = completion::CompletionResponse;
+    type Error = SendRequestError;
+    async fn send(self) -> Result<Self::Response, Self::Error> {
+        self.send_detailed().await.map(|resp| resp.body)
+    }
+}
+
+impl SendRequest for Request<ChatState> {
+    type Response = chat::ChatResponse;
+    type Error = SendRequestError;
+
+    async fn send(self) -> Result<Self::Response, SendRequestError> {
+        self.send_detailed().await.map(|resp| resp.body)
+    }
+}
+
+impl SendRequest for Request<ResponsesState> {
+    type Response = responses::ResponsesResult;
+    type Error = SendRequestError;
+
+    async fn send(self) -> Result<Self::Response, SendRequestError> {
+        self.send_detailed().await.map(|resp| resp.body)
+    }
+}
+
+impl SendRequest for Request<EmbeddingState> {
+    type Response = embedding::EmbeddingResponse;
+    type Error = SendRequestError;
+
+    async fn send(self) -> Result<Self::Response, SendRequestError> {
+        self.send_detailed().await.map(|resp| resp.body)
+    }
+}
+
+impl SendRequest for Request<ModerationState> {
+    type Response = moderation::ModerationResponse;
+    type Error = SendRequestError;
+
+    async fn send(self) -> Result<Self::Response, SendRequestError> {
+        self.send_detailed().await.map(|resp| resp.body)
+    }
+}
+
+impl SendRequest for Request<ImageState> {
+    type Response = image::ImageResponse;
+    type Error = SendRequestError;
+
+    async fn send(self) -> Result<Self::Response, SendRequestError> {
+        self.send_detailed().await.map(|resp| resp.body)
+    }
+}
+
+impl SendRequest for Request<AudioState> {
+    type Response = audio::TranscriptionResponse;
+    type Error = SendRequestError;
+
+    async fn send(self) -> Result<Self::Response, SendRequestError> {
+        self.send_detailed().await.map(|resp| resp.body)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A builder for creating requests to the OpenAI API.
+pub struct RequestBuilder<T> {
+    req: serde_json::Value,
+    api_key: String,
+    config: RequestConfig,
+    check_message_lengths: bool,
+    state: std::marker::PhantomData<T>,
+}
+
+impl<C> RequestBuilder<C> {
+    /// Create a new request builder.
+    ///
+    /// The key may already start with `Bearer `, as it does when copied from a header, in which case
+    /// the prefix isn't added twice.
+    pub fn new<T: ToString, S: Display>(model: T, api_key: S) -> Self {
+        let api_key = api_key.to_string();
+        let api_key = api_key.trim();
+        let api_key = match api_key.get(..7) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("bearer ") => api_key[7..].trim_start(),
+            _ => api_key,
+        };
+        let api_key = format!("Bearer {api_key}");
+
+        let req = json!({
+            "model": model.to_string(),
+        });
+
+        Self {
+            req,
+            api_key,
+            config: RequestConfig::default(),
+            check_message_lengths: false,
+            state: std::marker::PhantomData,
+        }
+    }
+
+    /// Create a new request builder, reading the API key from the `OPENAI_API_KEY` environment variable.
+    ///
+    /// Returns [`BuildError::MissingApiKey`] if it is not set or empty.
+    pub fn from_env<T: ToString>(model: T) -> Result<Self, BuildError> {
+        Ok(Self::new(model, api_key_from_env()?))
+    }
+
+    /// Set the base URL requests are sent to, `https://api.openai.com/v1` by default.
+    ///
+    /// This allows using OpenAI-compatible servers, or a mock server in tests.
+    pub fn base_url<T: ToString>(mut self, base_url: T) -> Self {
+        self.config.base_url(base_url);
+        self
+    }
+
+    /// Set the `User-Agent` header, `rust-gpt/<version>` by default.
+    pub fn user_agent<T: ToString>(mut self, user_agent: T) -> Self {
+        self.config.user_agent(user_agent);
+        self
+    }
+
+    /// Set the `OpenAI-Organization` header, which decides which organization is billed for the request.
+    ///
+    /// The header is left out unless this is set, in which case your default organization is used.
+    pub fn organization<T: Into<String>>(mut self, organization: T) -> Self {
+        self.config.organization(organization);
+        self
+    }
+
+    /// Adds a header to the request, e.g. `OpenAI-Beta: assistants=v2` or one a gateway needs.
+    ///
+    /// Setting a header twice keeps the last value. Headers set here replace the ones the crate sets
+    /// itself, like `Authorization`, ignoring case.
+    pub fn header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.config.header(name, value);
+        self
+    }
+
+    /// Send the request with the given client instead of the one shared by all requests.
+    ///
+    /// This allows configuring proxies, timeouts and connection pooling.
+    /// The client is async, so `send_blocking` fails with [`SendRequestError::UnsupportedConfig`] when one is set.
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.config.client(client);
+        self
+    }
+
+    /// Send the request with the given [`Transport`](transport::Transport) instead of over HTTP,
+    /// e.g. a [`MockTransport`](transport::MockTransport) in tests.
+    ///
+    /// This takes precedence over [`client`](Self::client), and the [`timeout`](Self::timeout) is not applied.
+    /// Transports are async, so `send_blocking` fails with [`SendRequestError::UnsupportedConfig`] when one is set.
+    /// They also can't upload files, so audio requests fail the same way instead of going over HTTP.
+    pub fn transport<T: transport::Transport + 'static>(mut self, transport: T) -> Self {
+        self.config.transport(transport);
+        self
+    }
+
+    /// Set a timeout for each HTTP request, after which sending fails with a [`SendRequestError::ReqwestError`].
+    ///
+    /// There is no timeout by default. When retrying, every attempt gets the full timeout,
+    /// use [`Request::send_with_timeout`] to bound the whole call instead.
+    ///
+    /// [`Request::send_with_timeout`]: ./struct.Request.html#method.send_with_timeout
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout(timeout);
+        self
+    }
+
+    /// Retry the request when it is rate limited or hits a server error, see [`RetryConfig`].
+    ///
+    /// Requests are not retried by default.
+    pub fn retry(mut self, retry: RetryConfig) -> Self {
+        self.config.retry(retry);
+        self
+    }
+
+    /// Append every successful request and its response to a JSONL file.
+    ///
+    /// Each line holds a unix `timestamp`, the `request` body, the `response` body and its token `usage`.
+    /// Streamed responses are not logged.
+    pub fn log_to<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.config.log_to(path);
+        self
+    }
+
+    /// Sets any field of the request body, e.g. a parameter OpenAI added that has no method yet.
+    ///
+    /// The value replaces whatever was set for `key` before, including by other methods, and isn't
+    /// checked by the `try_build_*` methods.
+    pub fn raw_field(mut self, key: &str, value: serde_json::Value) -> Self {
+        self.req[key] = value;
+        self
+    }
+
+    pub(crate) fn config(mut self, config: RequestConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    fn into_request(self) -> Request<C> {
+        Request {
+            api_key: self.api_key,
+            to_send: self.req.to_string(),
+            file: None,
+            config: self.config,
+            state: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<C: CompletionLike> RequestBuilder<C> {
+    /// Checks parameters that are only valid in combination with each other.
+    fn validate(&self) -> Result<(), BuildError> {
+        let req = &self.req;
+
+        if !req["stream_options"].is_null() && req["stream"] != true {
+            return Err(BuildError::StreamOptionsWithoutStream);
+        }
+
+        if !req["tool_choice"].is_null() && req["tools"].is_null() {
+            return Err(BuildError::ToolChoiceWithoutTools);
+        }
+
+        validate_ranges(
+            req["temperature"].as_f64(),
+            req["top_p"].as_f64(),
+            req["presence_penalty"].as_f64(),
+            req["frequency_penalty"].as_f64(),
+        )?;
+
+        if let Some(stop) = req["stop"].as_array() {
+            validate_stop_sequences(stop.len())?;
+        }
+
+        if let Some(logit_bias) = req["logit_bias"].as_object() {
+            for (token, bias) in logit_bias {
+                let bias = bias.as_f64().unwrap_or_default() as f32;
+                if !(-100.0..=100.0).contains(&bias) {
+                    return Err(BuildError::LogitBiasOutOfRange {
+                        token: token.parse().unwrap_or_default(),
+                        bias,
+                    });
+                }
+            }
+        }
+
+        if let Some(best_of) = req["best_of"].as_u64() {
+            let n = req["n"].as_u64().unwrap_or(1);
+            if best_of < n {
+                return Err(BuildError::BestOfLessThanN { best_of, n });
+            }
+        }
+
+        if req["response_format"]["type"] == "json_schema" {
+            let model = req["model"].as_str().unwrap_or_default();
+            // only the gpt-4o generation and later support structured outputs
+            if model.starts_with("gpt-3.5") || model == "gpt-4" || model.starts_with("gpt-4-") {
+                return Err(BuildError::JsonSchemaUnsupported(model.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+    /// Set the max_tokens parameter.
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.req["max_tokens"] = json!(max_tokens);
+        self
+    }
+    /// Remove the max_tokens parameter so the API applies its own default, which lets the model
+    /// use whatever is left of its context window.
+    ///
+    /// Prefer this over guessing a large value, which fails if it exceeds the context window.
+    pub fn no_max_tokens(mut self) -> Self {
+        if let Some(req) = self.req.as_object_mut() {
+            req.remove("max_tokens");
+        }
+        self
+    }
+    /// Set the top_p parameter.
+    pub fn top_p<T: Into<f64>>(mut self, top_p: T) -> Self {
+        self.req["top_p"] = json!(top_p.into());
+        self
+    }
+    /// Set the frequency_penalty parameter.
+    pub fn frequency_penalty<T: Into<f64>>(mut self, frequency_penalty: T) -> Self {
+        self.req["frequency_penalty"] = json!(frequency_penalty.into());
+        self
     }
     /// Set the presence_penalty parameter.
-    pub fn presence_penalty(mut self, presence_penalty: f32) -> Self {
-        self.req["presence_penalty"] = json!(presence_penalty);
+    pub fn presence_penalty<T: Into<f64>>(mut self, presence_penalty: T) -> Self {
+        self.req["presence_penalty"] = json!(presence_penalty.into());
         self
     }
     /// Set the stop parameter.
+    ///
+    /// This is always sent as an array, matching what [`ChatBuilder::stop`] sends.
+    ///
+    /// [`ChatBuilder::stop`]: ./chat/struct.ChatBuilder.html#method.stop
     pub fn stop<T: ToString>(mut self, stop: T) -> Self {
-        self.req["stop"] = json!(stop.to_string());
+        self.req["stop"] = json!(stop_sequences([stop]));
         self
     }
-    /// Set the n parameter.
-    pub fn n(mut self, n: u32) -> Self {
-        self.req["n"] = json!(n);
+    /// Set several stop sequences at once.
+    ///
+    /// The API accepts at most [`MAX_STOP_SEQUENCES`], which is checked by the `try_build_*` methods.
+    /// The other build methods send them as they are and leave it to the API to reject the request.
+    ///
+    /// [`MAX_STOP_SEQUENCES`]: ./constant.MAX_STOP_SEQUENCES.html
+    pub fn stop_sequences<I, T>(mut self, stop: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: ToString,
+    {
+        self.req["stop"] = json!(stop_sequences(stop));
+        self
+    }
+    /// Set the logit_bias parameter, which maps token ids to a bias between -100 and 100 that is
+    /// added to the token's likelihood.
+    ///
+    /// The `try_build_*` methods check the range, values around ±1 nudge a token while ±100 bans
+    /// or forces it.
+    pub fn logit_bias(mut self, logit_bias: HashMap<u32, f32>) -> Self {
+        self.req["logit_bias"] = json!(logit_bias);
+        self
+    }
+    /// Set the stream parameter.
+    ///
+    /// When enabled the API answers with server-sent events instead of a single JSON body.
+    pub fn stream(mut self, stream: bool) -> Self {
+        self.req["stream"] = json!(stream);
         self
     }
-
     pub fn user(mut self, user: String) -> Self {
         self.req["user"] = json!(user);
         self
     }
 }
 
-impl RequestBuilder<CompletionState> {
-    /// Set the prompt parameter.
+impl<C: SamplingLike> RequestBuilder<C> {
+    /// Set the temperature parameter.
+    ///
+    /// Like the other sampling parameters it takes `f32` or `f64`, and `f64` values are sent unchanged.
+    pub fn temperature<T: Into<f64>>(mut self, temperature: T) -> Self {
+        self.req["temperature"] = json!(temperature.into());
+        self
+    }
+}
+
+impl<C: CountLike> RequestBuilder<C> {
+    /// Set the n parameter, the number of choices or images to generate.
+    pub fn n(mut self, n: u32) -> Self {
+        self.req["n"] = json!(n);
+        self
+    }
+}
+
+impl<C: PromptLike> RequestBuilder<C> {
+    /// Set the prompt parameter, the text to complete or the description of the images to generate.
     pub fn prompt<T: ToString>(mut self, prompt: T) -> Self {
         self.req["prompt"] = json!(prompt.to_string());
         self
     }
+}
+
+impl RequestBuilder<CompletionState> {
+    /// Set the prompt parameter to a batch of prompts.
+    ///
+    /// Accepts anything that iterates over string-like items (e.g. `&[&str]` or `Vec<String>`),
+    /// so prompts that are already held as slices don't need to be collected first.
+    ///
+    /// The response has `n` choices for every prompt. The choices for the prompt at position `i` have
+    /// [`index`](./completion/struct.CompletionChoice.html#structfield.index) `i * n` up to `i * n + n - 1`.
+    pub fn prompts<I>(mut self, prompts: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.req["prompt"] = string_array(prompts);
+        self
+    }
+    /// Set the best_of parameter, generating `best_of` completions on the server and returning the `n` best.
+    ///
+    /// `best_of` must be greater than or equal to `n`, which [`try_build_completion`] checks.
+    ///
+    /// [`try_build_completion`]: #method.try_build_completion
+    pub fn best_of(mut self, best_of: u32) -> Self {
+        self.req["best_of"] = json!(best_of);
+        self
+    }
+    /// Set the suffix parameter, the text that comes after the inserted completion.
+    pub fn suffix<T: ToString>(mut self, suffix: T) -> Self {
+        self.req["suffix"] = json!(suffix.to_string());
+        self
+    }
+    /// Set the echo parameter, which includes the prompt at the start of each completion.
+    pub fn echo(mut self, echo: bool) -> Self {
+        self.req["echo"] = json!(echo);
+        self
+    }
+    /// Set the logprobs parameter, returning the log probabilities of the `logprobs` most likely tokens
+    /// at each position, see [`CompletionChoice::logprobs`].
+    ///
+    /// The API allows at most 5. [`try_build_completion`] returns an error for anything higher,
+    /// [`build_completion`] sends it as it is.
+    ///
+    /// [`CompletionChoice::logprobs`]: ./completion/struct.CompletionChoice.html#structfield.logprobs
+    /// [`try_build_completion`]: #method.try_build_completion
+    /// [`build_completion`]: #method.build_completion
+    pub fn logprobs(mut self, logprobs: u8) -> Self {
+        self.req["logprobs"] = json!(logprobs);
+        self
+    }
     /// Builds a completion request.
     pub fn build_completion(self) -> Request<CompletionState> {
-        Request {
-            api_key: self.api_key,
-            to_send: self.req.to_string(),
-            state: std::marker::PhantomData,
+        self.into_request()
+    }
+    /// Builds a completion request, first checking that parameters which depend on each other are consistent.
+    pub fn try_build_completion(self) -> Result<Request<CompletionState>, BuildError> {
+        self.validate()?;
+
+        if let Some(logprobs) = self.req["logprobs"].as_u64() {
+            // values set with `raw_field` may not fit in the u8 `logprobs` takes
+            match u8::try_from(logprobs) {
+                Ok(logprobs) if logprobs > MAX_LOGPROBS => {
+                    return Err(BuildError::LogprobsTooHigh(logprobs));
+                }
+                Ok(_) => {}
+                Err(_) => {
+                    return Err(BuildError::OutOfRange {
+                        parameter: "logprobs",
+                        value: logprobs as f64,
+                        min: 0.0,
+                        max: MAX_LOGPROBS as f64,
+                    });
+                }
+            }
         }
+
+        Ok(self.build_completion())
     }
 }
 
 impl RequestBuilder<ChatState> {
-    /// Set the messages parameter.
-    pub fn messages(mut self, messages: Vec<chat::ChatMessage>) -> Self {
-        self.req["messages"] = json!(messages);
+    /// Set the messages parameter, replacing any messages that were already added.
+    pub fn messages<I: IntoIterator<Item = chat::ChatMessage>>(mut self, messages: I) -> Self {
+        self.req["messages"] = json!(messages.into_iter().collect::<Vec<_>>());
+        self
+    }
+
+    /// Appends a message to the messages parameter, e.g. to build a conversation step by step.
+    pub fn add_message(mut self, message: chat::ChatMessage) -> Self {
+        match self.req["messages"].as_array_mut() {
+            Some(messages) => messages.push(json!(message)),
+            None => self.req["messages"] = json!([message]),
+        }
+        self
+    }
+
+    /// Set the metadata parameter, which tags the request in the OpenAI dashboard when it is stored.
+    pub fn metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.req["metadata"] = json!(metadata);
+        self
+    }
+
+    /// Set the store parameter.
+    pub fn store(mut self, store: bool) -> Self {
+        self.req["store"] = json!(store);
+        self
+    }
+
+    /// Set the response_format parameter, e.g. to enable JSON mode.
+    pub fn response_format(mut self, response_format: chat::ResponseFormat) -> Self {
+        self.req["response_format"] = json!(response_format);
+        self
+    }
+
+    /// Set the functions parameter, describing the functions the model may call.
+    pub fn functions(mut self, functions: Vec<chat::FunctionDef>) -> Self {
+        self.req["functions"] = json!(functions);
+        self
+    }
+
+    /// Set the function_call parameter, which controls whether the model calls one of the functions.
+    pub fn function_call(mut self, function_call: chat::FunctionCallSetting) -> Self {
+        self.req["function_call"] = json!(function_call);
+        self
+    }
+
+    /// Set the tools parameter, describing the tools the model may call.
+    pub fn tools(mut self, tools: Vec<chat::Tool>) -> Self {
+        self.req["tools"] = json!(tools);
         self
     }
 
-    fn chat_parameters(mut self, chat_parameters: chat::ChatParameters) -> Self {
-        let mut params = json!(chat_parameters);
-        params["messages"] = self.req.get("messages").unwrap().clone();
-        params["model"] = self.req.get("model").unwrap().clone();
-        self.req = params;
+    /// Set the tool_choice parameter, which requires [`tools`](#method.tools) to be set as well.
+    pub fn tool_choice(mut self, tool_choice: chat::ToolChoice) -> Self {
+        self.req["tool_choice"] = json!(tool_choice);
+        self
+    }
+
+    /// Sets `stream_options.include_usage`, which makes the stream end with a
+    /// [`ChatStreamChunk::Usage`](chat::ChatStreamChunk::Usage) chunk holding the token usage.
+    ///
+    /// This requires streaming to be enabled with [`stream`](#method.stream), otherwise
+    /// [`try_build_chat`](#method.try_build_chat) fails and the API rejects the request.
+    pub fn stream_usage(mut self, include_usage: bool) -> Self {
+        if include_usage {
+            self.req["stream_options"] = json!({ "include_usage": true });
+        } else if let Some(req) = self.req.as_object_mut() {
+            req.remove("stream_options");
+        }
+        self
+    }
+
+    /// Sets every parameter that is set in `chat_parameters`, keeping everything else as it is.
+    ///
+    /// It doesn't matter whether this is called before or after the other methods.
+    #[doc(hidden)]
+    pub fn chat_parameters(mut self, chat_parameters: chat::ChatParameters) -> Self {
+        if let serde_json::Value::Object(params) = json!(chat_parameters) {
+            for (key, value) in params {
+                self.req[key] = value;
+            }
+        }
         self
     }
 
     /// Builds a chat request.
     pub fn build_chat(self) -> Request<ChatState> {
-        Request {
-            api_key: self.api_key,
-            to_send: self.req.to_string(),
-            state: std::marker::PhantomData,
+        self.into_request()
+    }
+
+    /// Builds a chat request, first checking that parameters which depend on each other are consistent.
+    pub fn try_build_chat(self) -> Result<Request<ChatState>, BuildError> {
+        self.validate()?;
+
+        if self.check_message_lengths {
+            self.validate_message_lengths()?;
         }
+
+        Ok(self.build_chat())
+    }
+
+    /// Makes [`try_build_chat`] check that no single message is longer than the model's context window.
+    ///
+    /// Token counts are estimated unless the `tiktoken` feature is enabled, and models with an
+    /// unknown context window are not checked.
+    /// This is off by default since it has to walk every message.
+    ///
+    /// [`try_build_chat`]: #method.try_build_chat
+    pub fn check_message_lengths(mut self, check: bool) -> Self {
+        self.check_message_lengths = check;
+        self
+    }
+
+    fn validate_message_lengths(&self) -> Result<(), BuildError> {
+        let model = self.req["model"].as_str().unwrap_or_default();
+
+        let Some(context_window) = tokens::context_window(model) else {
+            return Ok(());
+        };
+
+        let messages = self.req["messages"].as_array().map(Vec::as_slice);
+
+        for (index, message) in messages.unwrap_or_default().iter().enumerate() {
+            let tokens = match &message["content"] {
+                serde_json::Value::Array(parts) => parts
+                    .iter()
+                    .filter_map(|part| part["text"].as_str())
+                    .map(|text| tokens::count(model, text))
+                    .sum(),
+                content => tokens::count(model, content.as_str().unwrap_or_default()),
+            };
+
+            if tokens > context_window {
+                return Err(BuildError::MessageTooLong {
+                    index,
+                    tokens,
+                    model: model.to_string(),
+                    context_window,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<C: InputLike> RequestBuilder<C> {
+    /// Set the input parameter, the text to respond to, embed or moderate.
+    pub fn input<T: ToString>(mut self, input: T) -> Self {
+        self.req["input"] = json!(input.to_string());
+        self
+    }
+}
+
+impl<C: BatchInputLike> RequestBuilder<C> {
+    /// Set the input parameter to a batch of texts, which are handled in a single request.
+    ///
+    /// The response has one result for every input. For embeddings the
+    /// [`index`](./embedding/struct.Embedding.html#structfield.index) is the position of the input,
+    /// moderation results are in the same order as the inputs.
+    pub fn inputs<I>(mut self, inputs: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.req["input"] = string_array(inputs);
+        self
+    }
+}
+
+impl RequestBuilder<ResponsesState> {
+    /// Set the instructions parameter, which acts like a system message for this response only.
+    pub fn instructions<T: ToString>(mut self, instructions: T) -> Self {
+        self.req["instructions"] = json!(instructions.to_string());
+        self
+    }
+
+    /// Set the previous_response_id parameter to continue a conversation stored by OpenAI.
+    pub fn previous_response_id<T: ToString>(mut self, previous_response_id: T) -> Self {
+        self.req["previous_response_id"] = json!(previous_response_id.to_string());
+        self
+    }
+
+    /// Set the max_output_tokens parameter.
+    pub fn max_output_tokens(mut self, max_output_tokens: u32) -> Self {
+        self.req["max_output_tokens"] = json!(max_output_tokens);
+        self
+    }
+
+    /// Set the store parameter, which must be enabled for the response to be used as a `previous_response_id`.
+    pub fn store(mut self, store: bool) -> Self {
+        self.req["store"] = json!(store);
+        self
+    }
+
+    /// Builds a request to the responses endpoint.
+    pub fn build_responses(self) -> Request<ResponsesState> {
+        self.into_request()
+    }
+}
+
+impl RequestBuilder<EmbeddingState> {
+    /// Builds a request to the embeddings endpoint.
+    pub fn build_embedding(self) -> Request<EmbeddingState> {
+        self.into_request()
+    }
+}
+
+impl RequestBuilder<ModerationState> {
+    /// Builds a request to the moderation endpoint.
+    pub fn build_moderation(self) -> Request<ModerationState> {
+        self.into_request()
+    }
+}
+
+impl RequestBuilder<ImageState> {
+    /// Set the size parameter.
+    pub fn size(mut self, size: image::Size) -> Self {
+        self.req["size"] = json!(size);
+        self
+    }
+
+    /// Set the response_format parameter, which decides whether images are returned as links or base64 data.
+    pub fn response_format(mut self, response_format: image::ImageResponseFormat) -> Self {
+        self.req["response_format"] = json!(response_format);
+        self
+    }
+
+    /// Builds a request to the image generation endpoint.
+    pub fn build_image(self) -> Request<ImageState> {
+        self.into_request()
+    }
+}
+
+impl RequestBuilder<AudioState> {
+    /// Set the language parameter, the ISO-639-1 code of the spoken language, which improves accuracy and latency.
+    pub fn language<T: ToString>(mut self, language: T) -> Self {
+        self.req["language"] = json!(language.to_string());
+        self
+    }
+
+    /// Builds a request to the transcription endpoint for the audio in `file_bytes`, using the model the
+    /// builder was created with.
+    ///
+    /// OpenAI detects the format from the extension of `filename` (e.g. `note.m4a`), so it has to match the audio.
+    pub fn transcribe<B: Into<Vec<u8>>, T: Into<String>>(
+        self,
+        file_bytes: B,
+        filename: T,
+    ) -> Request<AudioState> {
+        let mut request = self.into_request();
+        request.file = Some(audio::AudioFile {
+            bytes: file_bytes.into(),
+            filename: filename.into(),
+        });
+        request
     }
 }
@@ -0,0 +1,49 @@
+//! # Moderation API
+//!
+//! Includes the structs that represent a response from the moderation API, which checks whether
+//! text violates OpenAI's usage policies, e.g. to screen user input before sending it to chat.
+use std::{collections::HashMap, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize)]
+/// Represents a response from the moderation API.
+pub struct ModerationResponse {
+    pub id: String,
+    pub model: String,
+    /// One result for every input, in the same order.
+    pub results: Vec<ModerationResult>,
+}
+
+impl ModerationResponse {
+    /// Returns `true` if any of the inputs was flagged.
+    pub fn flagged(&self) -> bool {
+        self.results.iter().any(|result| result.flagged)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+/// Represents the moderation result of one of the inputs.
+pub struct ModerationResult {
+    pub flagged: bool,
+    /// Whether the input violates each category, keyed by category name (e.g. `"self-harm/intent"`).
+    pub categories: HashMap<String, bool>,
+    /// The model's confidence for each category, between 0 and 1.
+    pub category_scores: HashMap<String, f64>,
+}
+
+impl TryFrom<serde_json::Value> for ModerationResponse {
+    type Error = serde_json::Error;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        Self::deserialize(value)
+    }
+}
+
+impl FromStr for ModerationResponse {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
@@ -0,0 +1,113 @@
+//! # Pricing
+//!
+//! Estimates what requests cost from their token [`Usage`].
+//!
+//! [`PricingTable::default`] holds OpenAI's list prices in US dollars for the models this crate knows.
+//! Prices change, so add or replace entries with [`PricingTable::set`] when they're out of date or
+//! you're billed differently.
+//!
+//! ```rust
+//! use rust_gpt::chat::Usage;
+//! use rust_gpt::pricing::{Pricing, PricingTable};
+//!
+//! let usage = Usage { prompt_tokens: 1000, completion_tokens: 500, total_tokens: 1500 };
+//! assert_eq!(usage.estimated_cost("gpt-4"), Some(0.06));
+//!
+//! let table = PricingTable::default().set("my-fine-tune", Pricing::new(0.012, 0.016));
+//! assert_eq!(table.cost("my-fine-tune", &usage), Some(0.02));
+//! ```
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+
+use crate::chat::Usage;
+
+/// The default table, used by [`Usage::estimated_cost`].
+pub(crate) static DEFAULT_PRICES: Lazy<PricingTable> = Lazy::new(PricingTable::default);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The price of a model per 1000 tokens.
+pub struct Pricing {
+    pub prompt_per_1k: f64,
+    pub completion_per_1k: f64,
+}
+
+impl Pricing {
+    pub fn new(prompt_per_1k: f64, completion_per_1k: f64) -> Self {
+        Self {
+            prompt_per_1k,
+            completion_per_1k,
+        }
+    }
+
+    /// Returns what `usage` costs at these prices.
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        (usage.prompt_tokens as f64 * self.prompt_per_1k
+            + usage.completion_tokens as f64 * self.completion_per_1k)
+            / 1000.0
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The prices of models, by model name.
+pub struct PricingTable {
+    prices: HashMap<String, Pricing>,
+}
+
+impl PricingTable {
+    /// Creates a table without any prices.
+    pub fn empty() -> Self {
+        Self {
+            prices: HashMap::new(),
+        }
+    }
+
+    /// Sets the price of `model`, replacing its current price.
+    pub fn set<T: Into<String>>(mut self, model: T, pricing: Pricing) -> Self {
+        self.prices.insert(model.into(), pricing);
+        self
+    }
+
+    /// Returns the price of `model`.
+    ///
+    /// Dated snapshots like `gpt-4-0613` fall back to the price of the model they're a snapshot of.
+    pub fn get(&self, model: &str) -> Option<Pricing> {
+        if let Some(pricing) = self.prices.get(model) {
+            return Some(*pricing);
+        }
+
+        let (base, date) = model.rsplit_once('-')?;
+        let is_snapshot = date.len() == 4 && date.bytes().all(|b| b.is_ascii_digit());
+
+        is_snapshot
+            .then(|| self.prices.get(base).copied())
+            .flatten()
+    }
+
+    /// Returns what `usage` costs with `model`, or `None` if its price isn't known.
+    pub fn cost(&self, model: &str, usage: &Usage) -> Option<f64> {
+        self.get(model).map(|pricing| pricing.cost(usage))
+    }
+}
+
+impl Default for PricingTable {
+    /// OpenAI's list prices for the models this crate knows.
+    fn default() -> Self {
+        [
+            ("gpt-3.5-turbo", Pricing::new(0.0015, 0.002)),
+            ("gpt-3.5-turbo-16k", Pricing::new(0.003, 0.004)),
+            ("gpt-3.5-turbo-instruct", Pricing::new(0.0015, 0.002)),
+            ("gpt-4", Pricing::new(0.03, 0.06)),
+            ("gpt-4-32k", Pricing::new(0.06, 0.12)),
+            ("text-davinci-003", Pricing::new(0.02, 0.02)),
+            ("text-davinci-002", Pricing::new(0.02, 0.02)),
+            ("babbage-002", Pricing::new(0.0004, 0.0004)),
+            ("davinci-002", Pricing::new(0.002, 0.002)),
+            ("text-embedding-ada-002", Pricing::new(0.0001, 0.0)),
+        ]
+        .into_iter()
+        .fold(Self::empty(), |table, (model, pricing)| {
+            table.set(model, pricing)
+        })
+    }
+}
@@ -0,0 +1,69 @@
+//! # Prompt templates
+//!
+//! A lightweight helper for reusing prompts with placeholders, without reaching for a template engine.
+//!
+//! ```rust
+//! use rust_gpt::prompt::Prompt;
+//!
+//! let prompt = Prompt::template("Summarize {text} in {n} words.").fill([("text", "this"), ("n", "3")]);
+//! assert_eq!(prompt, "Summarize this in 3 words.");
+//! ```
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+/// A prompt containing `{key}` placeholders.
+///
+/// Use `{{` and `}}` for literal braces.
+pub struct Prompt {
+    template: String,
+}
+
+impl Prompt {
+    /// Creates a prompt from a template string.
+    pub fn template<T: ToString>(template: T) -> Self {
+        Self {
+            template: template.to_string(),
+        }
+    }
+
+    /// Replaces every `{key}` placeholder with its value, producing the final prompt.
+    ///
+    /// Placeholders without a value are left as they are.
+    pub fn fill<I, K, V>(&self, values: I) -> String
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: ToString,
+        V: ToString,
+    {
+        let values: HashMap<String, String> = values
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+
+        let mut out = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+
+        while let Some(pos) = rest.find(['{', '}']) {
+            out.push_str(&rest[..pos]);
+            let brace = &rest[pos..];
+
+            if brace.starts_with("{{") || brace.starts_with("}}") {
+                out.push_str(&brace[..1]);
+                rest = &brace[2..];
+            } else if let (true, Some(end)) = (brace.starts_with('{'), brace.find('}')) {
+                let key = &brace[1..end];
+                match values.get(key) {
+                    Some(value) => out.push_str(value),
+                    None => out.push_str(&brace[..=end]),
+                }
+                rest = &brace[end + 1..];
+            } else {
+                out.push_str(&brace[..1]);
+                rest = &brace[1..];
+            }
+        }
+
+        out.push_str(rest);
+        out
+    }
+}
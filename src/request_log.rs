@@ -0,0 +1,78 @@
+//! Appends request/response pairs to a JSONL file, see [`RequestBuilder::log_to`](crate::RequestBuilder::log_to).
+
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::json;
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug)]
+/// A request waiting for its response to be logged.
+pub(crate) struct RequestLog {
+    path: PathBuf,
+    request: String,
+}
+
+impl RequestLog {
+    pub(crate) fn new(path: PathBuf, request: String) -> Self {
+        Self { path, request }
+    }
+
+    /// Appends the request and its response as a single line.
+    ///
+    /// Failing to write the log shouldn't fail a request that already succeeded, so errors are ignored.
+    pub(crate) async fn write(&self, response: &serde_json::Value) {
+        let line = self.line(response);
+
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await;
+
+        if let Ok(mut file) = file {
+            // tokio finishes writes in the background, so flush to make sure the line is written
+            let _ = file.write_all(line.as_bytes()).await;
+            let _ = file.flush().await;
+        }
+    }
+
+    /// Like [`write`](Self::write), but blocks the current thread.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn write_blocking(&self, response: &serde_json::Value) {
+        use std::io::Write;
+
+        let line = self.line(response);
+
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path);
+
+        if let Ok(mut file) = file {
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+
+    fn line(&self, response: &serde_json::Value) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let request = serde_json::from_str::<serde_json::Value>(&self.request)
+            .unwrap_or_else(|_| self.request.clone().into());
+
+        let mut line = json!({
+            "timestamp": timestamp,
+            "request": request,
+            "response": response,
+            "usage": response["usage"],
+        })
+        .to_string();
+        line.push('\n');
+        line
+    }
+}
@@ -0,0 +1,92 @@
+//! # Responses API
+//!
+//! The responses API is OpenAI's newer, unified endpoint which can keep conversation state on the server.
+//! Instead of sending the whole history each time, a response can be continued with [`previous_response_id`].
+//!
+//! Only text input and output are supported for now.
+//!
+//! [`previous_response_id`]: ../struct.RequestBuilder.html#method.previous_response_id
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize)]
+/// Represents a response from the responses API.
+pub struct ResponsesResult {
+    pub id: String,
+    pub object: String,
+    pub created_at: u64,
+    pub model: String,
+    pub status: Option<String>,
+    pub output: Vec<OutputItem>,
+    pub usage: Option<ResponsesUsage>,
+}
+
+impl ResponsesResult {
+    /// Joins the text of every `output_text` part in the output.
+    pub fn output_text(&self) -> String {
+        self.output
+            .iter()
+            .flat_map(|item| match item {
+                OutputItem::Message { content, .. } => content.as_slice(),
+                OutputItem::Other => &[],
+            })
+            .filter_map(|content| match content {
+                OutputContent::OutputText { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl TryFrom<serde_json::Value> for ResponsesResult {
+    type Error = serde_json::Error;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        Self::deserialize(value)
+    }
+}
+
+impl FromStr for ResponsesResult {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// Represents one of the items in the output of a response.
+pub enum OutputItem {
+    Message {
+        id: String,
+        role: String,
+        content: Vec<OutputContent>,
+    },
+    /// An output item this crate does not model yet, like a tool call.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// Represents one of the content parts of an output message.
+pub enum OutputContent {
+    OutputText {
+        text: String,
+    },
+    Refusal {
+        refusal: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+/// Represents the usage information returned by the responses API.
+pub struct ResponsesUsage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub total_tokens: u32,
+}
@@ -0,0 +1,98 @@
+//! Parsing of the server-sent events used by streamed responses.
+
+use futures_util::{stream, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+
+use crate::{JsonParseError, OpenAiApiError, SendRequestError};
+
+/// Turns the body of a streamed response into a stream of the JSON events it contains.
+pub(crate) fn events<T, S, B>(
+    bytes: S,
+) -> impl Stream<Item = Result<T, SendRequestError>> + Send + Unpin
+where
+    T: DeserializeOwned + Send,
+    S: Stream<Item = reqwest::Result<B>> + Send + 'static,
+    B: AsRef<[u8]>,
+{
+    let reader = EventReader {
+        bytes: Box::pin(bytes),
+        buffer: Vec::new(),
+        eof: false,
+    };
+
+    Box::pin(stream::unfold(reader, |mut reader| async move {
+        let data = reader.next_data().await?;
+
+        Some((data.and_then(|data| parse(&data)), reader))
+    }))
+}
+
+struct EventReader<S> {
+    bytes: S,
+    buffer: Vec<u8>,
+    eof: bool,
+}
+
+impl<S, B> EventReader<S>
+where
+    S: Stream<Item = reqwest::Result<B>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    /// Returns the payload of the next `data:` line, or `None` once the stream is done.
+    ///
+    /// Lines can be split across chunks of the body, so bytes are buffered until a full line arrives.
+    async fn next_data(&mut self) -> Option<Result<String, SendRequestError>> {
+        loop {
+            if let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line);
+
+                // blank keep-alive lines, comments and other fields are skipped
+                let Some(data) = line.trim_end().strip_prefix("data:") else {
+                    continue;
+                };
+
+                let data = data.trim_start();
+                if data == "[DONE]" {
+                    self.eof = true;
+                    self.buffer.clear();
+                    return None;
+                }
+
+                return Some(Ok(data.to_string()));
+            }
+
+            if self.eof {
+                return None;
+            }
+
+            match self.bytes.next().await {
+                Some(Ok(bytes)) => self.buffer.extend_from_slice(bytes.as_ref()),
+                Some(Err(e)) => {
+                    self.eof = true;
+                    self.buffer.clear();
+                    return Some(Err(e.into()));
+                }
+                None => {
+                    // make sure a last line without a trailing newline still gets read
+                    self.eof = true;
+                    self.buffer.push(b'\n');
+                }
+            }
+        }
+    }
+}
+
+fn parse<T: DeserializeOwned>(data: &str) -> Result<T, SendRequestError> {
+    use SendRequestError::*;
+
+    let json_error = |e| JsonError(JsonParseError::new(data, Some(e)));
+
+    let json: serde_json::Value = serde_json::from_str(data).map_err(json_error)?;
+
+    if !json["error"].is_null() {
+        return Err(OpenAiError(OpenAiApiError::from_json(&json)));
+    }
+
+    T::deserialize(json).map_err(json_error)
+}
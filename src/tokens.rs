@@ -0,0 +1,132 @@
+//! # Tokens
+//!
+//! Token accounting used to catch requests that can't fit a model's context window.
+//!
+//! Token counts are estimated from the length of the text, unless the `tiktoken` feature is enabled,
+//! which adds [`count_tokens`] and [`count_message_tokens`] to count them exactly with OpenAI's BPE tables.
+use crate::chat::{ChatMessage, Content};
+
+/// Estimates the number of tokens in `text`.
+///
+/// English text averages about four characters per token, which is close enough to flag
+/// messages that are far too long without shipping a tokenizer.
+pub(crate) fn estimate(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Counts the tokens in `text` if the `tiktoken` feature is enabled, otherwise estimates them.
+#[cfg(feature = "tiktoken")]
+pub(crate) fn count(model: &str, text: &str) -> usize {
+    count_tokens(model, text)
+}
+
+/// Counts the tokens in `text` if the `tiktoken` feature is enabled, otherwise estimates them.
+#[cfg(not(feature = "tiktoken"))]
+pub(crate) fn count(_model: &str, text: &str) -> usize {
+    estimate(text)
+}
+
+/// The tokens OpenAI wraps around every chat message, including its role.
+///
+/// Every model but `gpt-3.5-turbo-0301` uses 3 for the formatting and 1 for the role.
+pub(crate) const MESSAGE_OVERHEAD: usize = 4;
+
+/// The tokens that prime every chat reply.
+pub(crate) const REPLY_PRIMING: usize = 3;
+
+/// Counts or estimates the prompt tokens `messages` use in a chat request, including the formatting
+/// around each message, like [`count_message_tokens`] does when the `tiktoken` feature is enabled.
+pub(crate) fn count_messages<'a, I>(model: &str, messages: I) -> usize
+where
+    I: IntoIterator<Item = &'a ChatMessage>,
+{
+    let tokens: usize = messages
+        .into_iter()
+        .map(|message| {
+            let content: usize = message
+                .content
+                .iter()
+                .flat_map(Content::texts)
+                .map(|text| count(model, text))
+                .sum();
+            let name = message
+                .name
+                .as_deref()
+                .map_or(0, |name| count(model, name) + 1);
+
+            MESSAGE_OVERHEAD + content + name
+        })
+        .sum();
+
+    tokens + REPLY_PRIMING
+}
+
+/// Counts the tokens in `text` with the tokenizer `model` uses.
+///
+/// Models tiktoken doesn't know are counted with `cl100k_base`, the tokenizer of the GPT-3.5 and GPT-4 chat models.
+#[cfg(feature = "tiktoken")]
+pub fn count_tokens(model: &str, text: &str) -> usize {
+    use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+
+    let bpe = match get_tokenizer(model) {
+        Some(Tokenizer::O200kBase) => tiktoken_rs::o200k_base_singleton(),
+        Some(Tokenizer::P50kBase) => tiktoken_rs::p50k_base_singleton(),
+        Some(Tokenizer::P50kEdit) => tiktoken_rs::p50k_edit_singleton(),
+        Some(Tokenizer::R50kBase | Tokenizer::Gpt2) => tiktoken_rs::r50k_base_singleton(),
+        Some(Tokenizer::Cl100kBase) | None => tiktoken_rs::cl100k_base_singleton(),
+    };
+
+    let tokens = bpe.lock().encode_ordinary(text).len();
+    tokens
+}
+
+/// Counts the prompt tokens `messages` use in a chat request to `model`.
+///
+/// Besides the role, content and name of each message this includes the formatting OpenAI wraps
+/// around every message and the tokens that prime the reply, following
+/// [OpenAI's guide](https://github.com/openai/openai-cookbook/blob/main/examples/How_to_count_tokens_with_tiktoken.ipynb).
+#[cfg(feature = "tiktoken")]
+pub fn count_message_tokens(model: &str, messages: &[ChatMessage]) -> usize {
+    // gpt-3.5-turbo-0301 formats messages slightly differently from every later model
+    let (per_message, per_name) = if model == "gpt-3.5-turbo-0301" {
+        (4, -1)
+    } else {
+        (3, 1)
+    };
+
+    let tokens: isize = messages
+        .iter()
+        .map(|message| {
+            let mut tokens = per_message + count_tokens(model, &message.role.to_string()) as isize;
+
+            for text in message.content.iter().flat_map(Content::texts) {
+                tokens += count_tokens(model, text) as isize;
+            }
+            if let Some(name) = &message.name {
+                tokens += count_tokens(model, name) as isize + per_name;
+            }
+
+            tokens
+        })
+        .sum();
+
+    // every reply is primed with <|start|>assistant<|message|>
+    (tokens + 3) as usize
+}
+
+/// The context window assumed for models with an unknown context window, the smallest of the known ones.
+pub(crate) const FALLBACK_CONTEXT_WINDOW: usize = 4096;
+
+/// Returns the context window of a known model, in tokens.
+pub(crate) fn context_window(model: &str) -> Option<usize> {
+    match model {
+        "gpt-3.5-turbo" | "gpt-3.5-turbo-0301" | "gpt-3.5-turbo-instruct" => Some(4096),
+        "text-davinci-003" | "text-davinci-002" => Some(4097),
+        "code-davinci-002" => Some(8001),
+        "babbage-002" | "davinci-002" => Some(16384),
+        "gpt-3.5-turbo-16k" => Some(16384),
+        "gpt-4" | "gpt-4-0314" => Some(8192),
+        "gpt-4-32k" | "gpt-4-32k-0314" => Some(32768),
+        _ => None,
+    }
+}
@@ -0,0 +1,170 @@
+//! # Transport
+//!
+//! By default requests are sent with [reqwest](https://docs.rs/reqwest). A [`Transport`] replaces that,
+//! so code using this crate can be tested without a network connection or an API key.
+//!
+//! [`MockTransport`] answers requests with responses queued in advance and records what was sent.
+//!
+//! ```rust
+//! use std::sync::Arc;
+//!
+//! use rust_gpt::transport::MockTransport;
+//! use rust_gpt::{CompletionModel, RequestBuilder, SendRequest};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let mock = Arc::new(MockTransport::new());
+//! mock.respond(200, r#"{
+//!     "id": "cmpl-123",
+//!     "object": "text_completion",
+//!     "created": 1589478378,
+//!     "model": "gpt-3.5-turbo-instruct",
+//!     "choices": [{"text": "Hi!", "index": 0, "logprobs": null, "finish_reason": "stop"}],
+//!     "usage": {"prompt_tokens": 2, "completion_tokens": 2, "total_tokens": 4}
+//! }"#);
+//!
+//! let resp = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+//!     .transport(mock.clone())
+//!     .prompt("Say hi")
+//!     .build_completion()
+//!     .send()
+//!     .await
+//!     .unwrap();
+//!
+//! assert_eq!(resp.first_text(), Some("Hi!"));
+//! assert_eq!(mock.requests()[0].json()["prompt"], "Say hi");
+//! # }
+//! ```
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use reqwest::StatusCode;
+
+use crate::SendRequestError;
+
+/// The future returned by [`Transport::post`].
+pub type TransportFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<(StatusCode, String), SendRequestError>> + Send + 'a>>;
+
+/// Sends the body of a request and returns the status code and body of the response.
+///
+/// Set one with [`RequestBuilder::transport`](crate::RequestBuilder::transport) or
+/// [`ChatBuilder::transport`](crate::chat::ChatBuilder::transport).
+pub trait Transport: Send + Sync {
+    /// Posts `body` to `url` with the given headers.
+    fn post<'a>(
+        &'a self,
+        url: &'a str,
+        headers: &'a [(&'a str, &'a str)],
+        body: &'a str,
+    ) -> TransportFuture<'a>;
+}
+
+impl Debug for dyn Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Transport")
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn post<'a>(
+        &'a self,
+        url: &'a str,
+        headers: &'a [(&'a str, &'a str)],
+        body: &'a str,
+    ) -> TransportFuture<'a> {
+        (**self).post(url, headers, body)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A request received by a [`MockTransport`].
+pub struct MockRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl MockRequest {
+    /// Returns the value of the header with the given name, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parses the body as JSON.
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap()
+    }
+}
+
+#[derive(Debug, Default)]
+/// A [`Transport`] that answers requests with queued responses, in order.
+///
+/// Wrap it in an [`Arc`] to keep a handle to it after passing it to a builder.
+///
+/// # Panics
+/// Sending a request panics if there are no responses left.
+pub struct MockTransport {
+    responses: Mutex<VecDeque<(StatusCode, String)>>,
+    requests: Mutex<Vec<MockRequest>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a response with the given status code and body.
+    ///
+    /// # Panics
+    /// Panics if `status` isn't a valid status code.
+    pub fn respond<T: Into<String>>(&self, status: u16, body: T) -> &Self {
+        let status = StatusCode::from_u16(status).expect("invalid status code");
+
+        self.responses
+            .lock()
+            .unwrap()
+            .push_back((status, body.into()));
+        self
+    }
+
+    /// Returns the requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Transport for MockTransport {
+    fn post<'a>(
+        &'a self,
+        url: &'a str,
+        headers: &'a [(&'a str, &'a str)],
+        body: &'a str,
+    ) -> TransportFuture<'a> {
+        self.requests.lock().unwrap().push(MockRequest {
+            url: url.to_string(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: body.to_string(),
+        });
+
+        let response = self
+            .responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .expect("MockTransport has no responses left");
+
+        Box::pin(async move { Ok(response) })
+    }
+}
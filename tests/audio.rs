@@ -0,0 +1,66 @@
+mod common;
+
+use common::{MockResponse, MockServer};
+use rust_gpt::{
+    audio::TranscriptionResponse, transport::MockTransport, AudioModel, RequestBuilder,
+    SendRequest, SendRequestError,
+};
+
+const SILENCE: &[u8] = include_bytes!("fixtures/silence.wav");
+
+#[test]
+fn transcription_response_deserializes() {
+    let resp: TranscriptionResponse = r#"{"text": "Hello there."}"#.parse().unwrap();
+
+    assert_eq!(resp.text, "Hello there.");
+}
+
+#[tokio::test]
+async fn audio_is_uploaded_as_a_form() {
+    let server = MockServer::start(vec![MockResponse::json(200, r#"{"text": ""}"#)]).await;
+
+    let resp = RequestBuilder::new(AudioModel::Whisper1, "key")
+        .base_url(server.url())
+        .language("en")
+        .temperature(0.5)
+        .transcribe(SILENCE, "silence.wav")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.text, "");
+
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/audio/transcriptions");
+    assert!(request
+        .header("content-type")
+        .unwrap()
+        .starts_with("multipart/form-data; boundary="));
+    assert_eq!(request.header("authorization"), Some("Bearer key"));
+
+    let body = &request.body;
+    assert!(body.contains("name=\"model\"\r\n\r\nwhisper-1\r\n"));
+    assert!(body.contains("name=\"language\"\r\n\r\nen\r\n"));
+    assert!(body.contains("name=\"temperature\"\r\n\r\n0.5\r\n"));
+    assert!(body.contains("name=\"file\"; filename=\"silence.wav\""));
+    assert!(body.contains("RIFF"));
+    assert!(body.contains("WAVEfmt "));
+}
+
+#[tokio::test]
+async fn audio_is_not_uploaded_around_a_transport() {
+    let server = MockServer::start(vec![MockResponse::json(200, r#"{"text": ""}"#)]).await;
+    let mock = std::sync::Arc::new(MockTransport::new());
+
+    let err = RequestBuilder::new(AudioModel::Whisper1, "key")
+        .base_url(server.url())
+        .transport(mock.clone())
+        .transcribe(SILENCE, "silence.wav")
+        .send()
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, SendRequestError::UnsupportedConfig(_)));
+    assert!(mock.requests().is_empty());
+    assert!(server.requests().is_empty());
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::{MockResponse, MockServer, COMPLETION_RESPONSE};
+use rust_gpt::{transport::MockTransport, CompletionModel, RequestBuilder, SendRequestError};
+
+#[test]
+fn send_blocking_works_without_a_runtime() {
+    // the mock server needs a runtime, but the request is sent from outside of it
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let server = runtime.block_on(MockServer::start(vec![MockResponse::json(
+        200,
+        COMPLETION_RESPONSE,
+    )]));
+
+    let resp = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .base_url(server.url())
+        .prompt("Say hi")
+        .build_completion()
+        .send_blocking()
+        .unwrap();
+
+    assert_eq!(resp.choices[0].text, "This is indeed a test");
+    assert_eq!(server.requests()[0].path, "/completions");
+}
+
+#[test]
+fn send_blocking_rejects_async_transports_and_clients() {
+    let mock = std::sync::Arc::new(MockTransport::new());
+    mock.respond(200, COMPLETION_RESPONSE);
+    let builder = || {
+        RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+            // nothing may reach this, the request has to fail before it is sent
+            .base_url("http://127.0.0.1:9")
+            .prompt("Say hi")
+    };
+
+    let err = builder()
+        .transport(mock.clone())
+        .build_completion()
+        .send_blocking()
+        .unwrap_err();
+    assert!(matches!(err, SendRequestError::UnsupportedConfig(_)));
+    assert!(mock.requests().is_empty());
+
+    let err = builder()
+        .client(reqwest::Client::new())
+        .build_completion()
+        .send_blocking()
+        .unwrap_err();
+    assert!(matches!(err, SendRequestError::UnsupportedConfig(_)));
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{MockResponse, MockServer, COMPLETION_RESPONSE};
+use rust_gpt::{CompletionModel, RequestBuilder, SendRequestError};
+use tokio_util::sync::CancellationToken;
+
+#[tokio::test]
+async fn cancelling_stops_a_request_before_the_server_responds() {
+    let server = MockServer::start(vec![
+        MockResponse::json(200, COMPLETION_RESPONSE).delay(Duration::from_secs(5))
+    ])
+    .await;
+    let token = CancellationToken::new();
+
+    let cancel = token.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        cancel.cancel();
+    });
+
+    let start = Instant::now();
+    let err = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .base_url(server.url())
+        .prompt("Say hi")
+        .build_completion()
+        .send_cancellable(&token)
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, SendRequestError::Cancelled));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn cancelled_tokens_stop_requests_before_they_are_sent() {
+    let server = MockServer::start(vec![MockResponse::json(200, COMPLETION_RESPONSE)]).await;
+    let token = CancellationToken::new();
+    token.cancel();
+
+    let err = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .base_url(server.url())
+        .prompt("Say hi")
+        .build_completion()
+        .send_cancellable(&token)
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, SendRequestError::Cancelled));
+    assert!(server.requests().is_empty());
+}
@@ -0,0 +1,1129 @@
+mod common;
+
+use std::collections::HashMap;
+
+use serde_json::json;
+
+use common::{MockResponse, MockServer, CHAT_RESPONSE};
+use rust_gpt::chat::*;
+
+#[test]
+fn chat_parameters_stop_serializes_as_array() {
+    let params = ChatParameters {
+        stop: Some(vec!["\n".to_string()].into()),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        serde_json::to_value(params).unwrap(),
+        serde_json::json!({ "stop": ["\n"] })
+    );
+}
+
+#[test]
+fn chat_parameters_stop_deserializes_from_a_string_or_an_array() {
+    let single: ChatParameters = serde_json::from_str(r#"{"stop": "\n"}"#).unwrap();
+    assert_eq!(single.stop, Some(rust_gpt::Stop::Single("\n".to_string())));
+
+    let multiple: ChatParameters = serde_json::from_str(r#"{"stop": ["a", "b"]}"#).unwrap();
+    let stop = multiple.stop.unwrap();
+    assert_eq!(stop.len(), 2);
+    assert_eq!(stop.sequences(), ["a", "b"]);
+
+    let reloaded: ChatParameters = serde_json::from_value(
+        serde_json::to_value(ChatParameters {
+            stop: Some("END".into()),
+            ..Default::default()
+        })
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(reloaded.stop, Some("END".into()));
+}
+
+#[test]
+fn chat_models_pass_validation() {
+    assert!(
+        ChatBuilder::new(rust_gpt::ChatModel::Gpt4, "key".to_string())
+            .try_build()
+            .is_ok()
+    );
+    assert!(ChatBuilder::new(
+        rust_gpt::ChatModel::Custom("gpt-3.5-turbo-1106".into()),
+        "key".to_string()
+    )
+    .try_build()
+    .is_ok());
+}
+
+#[test]
+fn completion_models_fail_chat_validation() {
+    let err = ChatBuilder::new(
+        rust_gpt::ChatModel::Custom("text-davinci-003".into()),
+        "key".to_string(),
+    )
+    .try_build()
+    .err()
+    .unwrap();
+
+    assert_eq!(
+        err,
+        rust_gpt::BuildError::NotAChatModel("text-davinci-003".into())
+    );
+}
+
+#[test]
+fn tool_choice_serializes_each_form() {
+    use serde_json::json;
+
+    assert_eq!(json!(ToolChoice::None), json!("none"));
+    assert_eq!(json!(ToolChoice::Auto), json!("auto"));
+    assert_eq!(json!(ToolChoice::Required), json!("required"));
+    assert_eq!(
+        json!(ToolChoice::Function("get_weather".to_string())),
+        json!({"type": "function", "function": {"name": "get_weather"}})
+    );
+
+    for choice in [
+        ToolChoice::None,
+        ToolChoice::Auto,
+        ToolChoice::Required,
+        ToolChoice::Function("get_weather".to_string()),
+    ] {
+        let parsed: ToolChoice = serde_json::from_value(json!(choice)).unwrap();
+        assert_eq!(parsed, choice);
+    }
+}
+
+#[tokio::test]
+async fn chat_tools_round_trip_through_a_tool_call() {
+    let tool_call = r#"{
+        "id": "chatcmpl-123",
+        "created": 1677652288,
+        "choices": [{
+            "message": {
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_abc123",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}
+                }]
+            },
+            "finish_reason": "tool_calls"
+        }],
+        "usage": {"prompt_tokens": 9, "completion_tokens": 12, "total_tokens": 21}
+    }"#;
+    let server = MockServer::start(vec![
+        MockResponse::json(200, tool_call),
+        MockResponse::json(200, CHAT_RESPONSE),
+    ])
+    .await;
+    let weather = FunctionDef {
+        name: "get_weather".to_string(),
+        description: "Gets the weather in a city".to_string(),
+        parameters: json!({"type": "object", "properties": {"city": {"type": "string"}}}),
+    };
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .tools(vec![Tool::function(weather)])
+        .tool_choice(ToolChoice::Auto)
+        .try_build()
+        .unwrap();
+
+    let reply = chat
+        .send("What's the weather in Paris?", None)
+        .await
+        .unwrap();
+    let call = &reply.tool_calls.as_ref().unwrap()[0];
+    assert_eq!(call.function.name, "get_weather");
+
+    chat.submit_tool_result(call.id.clone(), "22C and sunny".to_string())
+        .await;
+    let answer = chat.get_response(None).await.unwrap();
+    assert_eq!(
+        answer.text(),
+        Some("Hello there, how may I assist you today?")
+    );
+
+    let requests = server.requests();
+    let first = requests[0].json();
+    assert_eq!(first["tools"][0]["type"], "function");
+    assert_eq!(first["tools"][0]["function"]["name"], "get_weather");
+    assert_eq!(first["tool_choice"], "auto");
+
+    let second = requests[1].json();
+    let messages = second["messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 4);
+    assert_eq!(messages[2]["tool_calls"][0]["id"], "call_abc123");
+    assert_eq!(messages[3]["role"], "tool");
+    assert_eq!(messages[3]["tool_call_id"], "call_abc123");
+    assert_eq!(second["tools"][0]["function"]["name"], "get_weather");
+}
+
+#[test]
+fn chat_tool_choice_requires_tools() {
+    let err = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .tool_choice(ToolChoice::Required)
+        .try_build()
+        .err()
+        .unwrap();
+
+    assert_eq!(err, rust_gpt::BuildError::ToolChoiceWithoutTools);
+}
+
+#[tokio::test]
+async fn submitted_tool_results_are_sent_without_a_new_question() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .build();
+
+    chat.ask("What's the weather in Paris?").await.unwrap();
+    chat.get_response(None).await.unwrap();
+
+    chat.submit_tool_result("call_abc123".to_string(), "22C and sunny".to_string())
+        .await;
+    chat.get_response(None).await.unwrap();
+
+    let body = server.requests()[1].json();
+    let messages = body["messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 4);
+    assert_eq!(messages[3]["role"], "tool");
+    assert_eq!(messages[3]["tool_call_id"], "call_abc123");
+    assert_eq!(messages[3]["content"], "22C and sunny");
+}
+
+#[test]
+fn too_many_stop_sequences_fail_validation() {
+    let err = ChatBuilder::new(rust_gpt::ChatModel::Gpt4, "key".to_string())
+        .stop_sequences(["a", "b", "c", "d", "e"])
+        .try_build()
+        .err()
+        .unwrap();
+
+    assert_eq!(err, rust_gpt::BuildError::TooManyStopSequences(5));
+}
+
+#[test]
+fn chat_message_constructors_set_the_role() {
+    let message = ChatMessage::new(Role::Assistant, "Hi!");
+    assert_eq!(message.role.to_string(), "assistant");
+    assert_eq!(message.text(), Some("Hi!"));
+
+    assert_eq!(ChatMessage::user("a").role.to_string(), "user");
+    assert_eq!(ChatMessage::system("a").role.to_string(), "system");
+    assert_eq!(ChatMessage::assistant("a").role.to_string(), "assistant");
+}
+
+#[test]
+fn tool_and_function_roles_round_trip() {
+    let message: ChatMessage = serde_json::from_str(
+        r#"{"role": "tool", "content": "22C and sunny", "tool_call_id": "call_abc123"}"#,
+    )
+    .unwrap();
+    assert!(matches!(message.role, Role::Tool));
+    assert_eq!(serde_json::to_value(&message).unwrap()["role"], "tool");
+
+    let message: ChatMessage =
+        serde_json::from_str(r#"{"role": "function", "content": "22C and sunny"}"#).unwrap();
+    assert!(matches!(message.role, Role::Function));
+    assert_eq!(serde_json::to_value(&message).unwrap()["role"], "function");
+}
+
+#[test]
+fn name_is_only_serialized_when_set() {
+    let mut message = ChatMessage::user("Hi!");
+    assert_eq!(
+        serde_json::to_value(&message).unwrap(),
+        serde_json::json!({"role": "user", "content": "Hi!"})
+    );
+
+    message.name = Some("alice".to_string());
+    assert_eq!(
+        serde_json::to_value(&message).unwrap(),
+        serde_json::json!({"role": "user", "content": "Hi!", "name": "alice"})
+    );
+}
+
+#[test]
+fn function_call_setting_serializes_each_form() {
+    use serde_json::json;
+
+    assert_eq!(json!(FunctionCallSetting::None), json!("none"));
+    assert_eq!(json!(FunctionCallSetting::Auto), json!("auto"));
+    assert_eq!(
+        json!(FunctionCallSetting::Function("get_weather".to_string())),
+        json!({"name": "get_weather"})
+    );
+}
+
+#[tokio::test]
+async fn function_calls_are_sent_and_deserialized() {
+    let server = MockServer::start(vec![MockResponse::json(
+        200,
+        r#"{
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1677652288,
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "function_call": {"name": "get_weather", "arguments": "{\"city\": \"Paris\"}"}
+                },
+                "finish_reason": "function_call"
+            }],
+            "usage": {"prompt_tokens": 9, "completion_tokens": 12, "total_tokens": 21}
+        }"#,
+    )])
+    .await;
+
+    let resp = rust_gpt::RequestBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key")
+        .base_url(server.url())
+        .messages(vec![ChatMessage::user("What's the weather in Paris?")])
+        .functions(vec![FunctionDef {
+            name: "get_weather".to_string(),
+            description: "Gets the current weather".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {"city": {"type": "string"}},
+            }),
+        }])
+        .function_call(FunctionCallSetting::Auto)
+        .build_chat()
+        .send_detailed()
+        .await
+        .unwrap()
+        .body;
+
+    let body = server.requests()[0].json();
+    assert_eq!(body["functions"][0]["name"], "get_weather");
+    assert_eq!(body["function_call"], "auto");
+
+    let call = resp.choices[0].message.function_call.as_ref().unwrap();
+    assert_eq!(call.name, "get_weather");
+    assert_eq!(call.arguments, r#"{"city": "Paris"}"#);
+}
+
+#[test]
+fn response_format_serializes_each_form() {
+    use serde_json::json;
+
+    assert_eq!(json!(ResponseFormat::Text), json!({"type": "text"}));
+    assert_eq!(
+        json!(ResponseFormat::JsonObject),
+        json!({"type": "json_object"})
+    );
+    assert_eq!(
+        json!(ResponseFormat::json_schema(
+            "answer",
+            json!({"type": "object"})
+        )),
+        json!({
+            "type": "json_schema",
+            "json_schema": {"name": "answer", "schema": {"type": "object"}}
+        })
+    );
+}
+
+#[test]
+fn json_schema_is_rejected_for_models_without_structured_outputs() {
+    let format = || ResponseFormat::json_schema("answer", serde_json::json!({"type": "object"}));
+    let builder = |model| {
+        rust_gpt::RequestBuilder::new(model, "key")
+            .messages(vec![ChatMessage::user("Hello")])
+            .response_format(format())
+    };
+
+    let err = builder(rust_gpt::ChatModel::Gpt4)
+        .try_build_chat()
+        .unwrap_err();
+    assert_eq!(
+        err,
+        rust_gpt::BuildError::JsonSchemaUnsupported("gpt-4".to_string())
+    );
+
+    let req = builder(rust_gpt::ChatModel::Custom("gpt-4o".to_string()))
+        .try_build_chat()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_str(req.body()).unwrap();
+    assert_eq!(body["response_format"]["json_schema"]["name"], "answer");
+}
+
+#[tokio::test]
+async fn response_format_is_only_sent_when_set() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let builder = || {
+        rust_gpt::RequestBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key")
+            .base_url(server.url())
+            .messages(vec![ChatMessage::user("Answer in JSON.")])
+    };
+
+    builder().build_chat().send_detailed().await.unwrap();
+    builder()
+        .response_format(ResponseFormat::JsonObject)
+        .build_chat()
+        .send_detailed()
+        .await
+        .unwrap();
+
+    let requests = server.requests();
+    assert!(requests[0].json().get("response_format").is_none());
+    assert_eq!(
+        requests[1].json()["response_format"],
+        serde_json::json!({"type": "json_object"})
+    );
+}
+
+#[tokio::test]
+async fn responses_without_choices_are_errors() {
+    let server = MockServer::start(vec![MockResponse::json(
+        200,
+        r#"{
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1677652288,
+            "choices": [],
+            "usage": {"prompt_tokens": 9, "completion_tokens": 0, "total_tokens": 9}
+        }"#,
+    )])
+    .await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .build();
+
+    chat.ask("Something that gets flagged").await.unwrap();
+    let err = chat.get_response(None).await.unwrap_err();
+
+    assert_eq!(err.to_string(), "Response contained no choices");
+    assert_eq!(chat.get_messages().await.len(), 1);
+}
+
+#[tokio::test]
+async fn history_keeps_len_user_messages() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .len(2)
+        .build();
+
+    for question in ["one", "two", "three"] {
+        chat.ask(question).await.unwrap();
+        chat.get_response(None).await.unwrap();
+    }
+
+    let body = server.requests()[2].json();
+    let messages = body["messages"].as_array().unwrap();
+    // the system message, "two" and its answer, and "three"
+    assert_eq!(messages.len(), 4);
+    assert_eq!(messages[1]["content"], "two");
+    assert_eq!(messages[3]["content"], "three");
+}
+
+#[tokio::test]
+async fn history_is_trimmed_to_the_token_budget() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .max_context_tokens(100)
+        .build();
+
+    chat.ask(&"long ".repeat(100)).await.unwrap();
+    chat.get_response(None).await.unwrap();
+    chat.ask("short").await.unwrap();
+    chat.get_response(None).await.unwrap();
+
+    let body = server.requests()[1].json();
+    let messages = body["messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[1]["content"], "short");
+}
+
+#[tokio::test]
+async fn usage_is_accumulated_across_responses() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .build();
+
+    for question in ["one", "two"] {
+        chat.ask(question).await.unwrap();
+        chat.get_response(None).await.unwrap();
+    }
+
+    assert_eq!(
+        chat.total_usage().await,
+        Usage {
+            prompt_tokens: 18,
+            completion_tokens: 24,
+            total_tokens: 42,
+        }
+    );
+}
+
+#[tokio::test]
+async fn send_asks_and_responds_in_one_call() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .build();
+
+    let reply = chat.send("Hello!", None).await.unwrap();
+    assert_eq!(
+        reply.text(),
+        Some("Hello there, how may I assist you today?")
+    );
+
+    let messages = chat.get_messages().await;
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[1].text(), Some("Hello!"));
+    assert_eq!(messages[2].content, reply.content);
+}
+
+#[tokio::test]
+async fn reset_clears_history_and_queue() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .system(ChatMessage::system("Be brief."))
+        .build();
+
+    chat.send("Hello!", None).await.unwrap();
+    chat.ask("Still queued").await.unwrap();
+    chat.reset().await;
+
+    let messages = chat.get_messages().await;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].text(), Some("Be brief."));
+    assert!(chat.get_response(None).await.is_err());
+}
+
+#[tokio::test]
+async fn pop_last_removes_the_latest_exchange() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .build();
+
+    chat.send("one", None).await.unwrap();
+    chat.send("two", None).await.unwrap();
+
+    assert!(chat.pop_last().await);
+    let messages = chat.get_messages().await;
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[1].text(), Some("one"));
+
+    assert!(chat.pop_last().await);
+    assert!(!chat.pop_last().await);
+    assert_eq!(chat.get_messages().await.len(), 1);
+}
+
+#[tokio::test]
+async fn set_system_replaces_the_system_message() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .system(ChatMessage::system("You are a pirate."))
+        .build();
+
+    chat.send("Hello!", None).await.unwrap();
+    chat.set_system(ChatMessage::system("You are a butler."))
+        .await;
+
+    let messages = chat.get_messages().await;
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[0].text(), Some("You are a butler."));
+
+    chat.send("Hello again!", None).await.unwrap();
+    assert_eq!(
+        server.requests()[1].json()["messages"][0]["content"],
+        "You are a butler."
+    );
+}
+
+#[tokio::test]
+async fn with_messages_sees_the_system_message_and_history_in_order() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .system(ChatMessage::system("You are a pirate."))
+        .build();
+
+    chat.send("Hello!", None).await.unwrap();
+
+    let texts = chat
+        .with_messages(|messages| {
+            messages
+                .map(|msg| (msg.role.to_string(), msg.text().unwrap().to_string()))
+                .collect::<Vec<_>>()
+        })
+        .await;
+    assert_eq!(
+        texts,
+        [
+            ("system".to_string(), "You are a pirate.".to_string()),
+            ("user".to_string(), "Hello!".to_string()),
+            (
+                "assistant".to_string(),
+                "Hello there, how may I assist you today?".to_string()
+            ),
+        ]
+    );
+
+    let count = chat.with_messages(|messages| messages.count()).await;
+    assert_eq!(count, 3);
+}
+
+#[test]
+fn chat_parameters_are_range_checked() {
+    let err = ChatBuilder::new(rust_gpt::ChatModel::Gpt4, "key".to_string())
+        .temperature(3.0)
+        .try_build()
+        .err()
+        .unwrap();
+
+    assert_eq!(
+        err,
+        rust_gpt::BuildError::OutOfRange {
+            parameter: "temperature",
+            value: 3.0,
+            min: 0.0,
+            max: 2.0
+        }
+    );
+}
+
+#[tokio::test]
+async fn requests_without_messages_are_rejected_before_sending() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let builder = || {
+        rust_gpt::RequestBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key").base_url(server.url())
+    };
+
+    for req in [
+        builder().build_chat(),
+        builder().messages(vec![]).build_chat(),
+    ] {
+        match req.send_detailed().await.unwrap_err() {
+            rust_gpt::SendRequestError::OpenAiError(e) => {
+                assert_eq!(e.message, "No messages in request.")
+            }
+            other => panic!("expected a missing messages error, got {other}"),
+        }
+    }
+    assert!(server.requests().is_empty());
+
+    builder()
+        .messages(vec![ChatMessage::user("How many messages can I send?")])
+        .build_chat()
+        .send_detailed()
+        .await
+        .unwrap();
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn history_is_trimmed_to_fit_the_context_window() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .max_tokens(1000)
+        .fit_context_window(true)
+        .build();
+
+    // about 1200 tokens each, so only two of them fit next to the 1000 reserved for the reply
+    for word in ["one ", "two ", "six "] {
+        chat.ask(&word.repeat(1200)).await.unwrap();
+        chat.get_response(None).await.unwrap();
+    }
+
+    let requests = server.requests();
+    assert_eq!(requests[1].json()["messages"].as_array().unwrap().len(), 4);
+
+    let body = requests[2].json();
+    let messages = body["messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 4);
+    assert!(messages[1]["content"].as_str().unwrap().starts_with("two"));
+    assert!(messages[3]["content"].as_str().unwrap().starts_with("six"));
+}
+
+#[tokio::test]
+async fn no_max_tokens_omits_the_limit_but_still_fits_the_context_window() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .max_tokens(100)
+        .no_max_tokens()
+        .fit_context_window(true)
+        .build();
+
+    // about 1400 tokens each, so only two of them fit next to the default reserved for the reply
+    for word in ["one ", "two ", "six "] {
+        chat.ask(&word.repeat(1400)).await.unwrap();
+        chat.get_response(None).await.unwrap();
+    }
+
+    let requests = server.requests();
+    assert!(requests[0].json().get("max_tokens").is_none());
+
+    let body = requests[2].json();
+    let messages = body["messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 4);
+    assert!(messages[1]["content"].as_str().unwrap().starts_with("two"));
+    assert!(messages[3]["content"].as_str().unwrap().starts_with("six"));
+}
+
+#[tokio::test]
+async fn fitting_the_context_window_reserves_room_for_the_reply_without_max_tokens() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .fit_context_window(true)
+        .build();
+
+    // two of these fill 3600 of the 4096 tokens, which leaves less than DEFAULT_REPLY_TOKENS
+    for word in ["one ", "two "] {
+        chat.ask(&word.repeat(1800)).await.unwrap();
+        chat.get_response(None).await.unwrap();
+    }
+
+    let body = server.requests()[1].json();
+    assert!(body.get("max_tokens").is_none());
+    let messages = body["messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 2);
+    assert!(messages[1]["content"].as_str().unwrap().starts_with("two"));
+}
+
+#[tokio::test]
+async fn the_token_budget_counts_the_formatting_of_every_message() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .len(100)
+        .max_context_tokens(60)
+        .build();
+
+    // "hi" is a single token, but every message also costs a few tokens of formatting
+    for _ in 0..6 {
+        chat.send("hi", None).await.unwrap();
+    }
+
+    // all 12 messages would fit in 60 tokens without it, but with it only the last two exchanges do
+    let body = server.requests()[5].json();
+    assert_eq!(body["messages"].as_array().unwrap().len(), 6);
+}
+
+#[test]
+fn chat_parameters_can_be_applied_in_any_order() {
+    let params = || ChatParameters {
+        temperature: Some(0.5),
+        user: Some("user-123".to_string()),
+        ..Default::default()
+    };
+
+    let before = rust_gpt::RequestBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key")
+        .chat_parameters(params())
+        .messages(vec![ChatMessage::user("Hi")])
+        .build_chat();
+    let after = rust_gpt::RequestBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key")
+        .messages(vec![ChatMessage::user("Hi")])
+        .stream(true)
+        .chat_parameters(params())
+        .build_chat();
+
+    let before: serde_json::Value = serde_json::from_str(before.body()).unwrap();
+    let after: serde_json::Value = serde_json::from_str(after.body()).unwrap();
+    for body in [&before, &after] {
+        assert_eq!(body["model"], "gpt-3.5-turbo");
+        assert_eq!(body["messages"][0]["content"], "Hi");
+        assert_eq!(body["temperature"], 0.5);
+        assert_eq!(body["user"], "user-123");
+    }
+    assert_eq!(after["stream"], true);
+}
+
+#[tokio::test]
+async fn every_choice_is_returned_but_only_the_first_is_remembered() {
+    let response = r#"{
+        "id": "chatcmpl-123",
+        "object": "chat.completion",
+        "created": 1677652288,
+        "choices": [
+            {"index": 0, "message": {"role": "assistant", "content": "Hi!"}, "finish_reason": "stop"},
+            {"index": 1, "message": {"role": "assistant", "content": "Hello!"}, "finish_reason": "stop"}
+        ],
+        "usage": {"prompt_tokens": 9, "completion_tokens": 4, "total_tokens": 13}
+    }"#;
+    let server = MockServer::start(vec![MockResponse::json(200, response)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .n(2)
+        .build();
+
+    chat.ask("Greet me").await.unwrap();
+    let replies = chat.get_responses(None).await.unwrap();
+
+    let contents: Vec<_> = replies.iter().map(|reply| reply.text().unwrap()).collect();
+    assert_eq!(contents, ["Hi!", "Hello!"]);
+    assert_eq!(server.requests()[0].json()["n"], 2);
+
+    let history = chat.get_messages().await;
+    assert_eq!(history.len(), 3);
+    assert_eq!(history[2].text(), Some("Hi!"));
+}
+
+#[test]
+fn tool_calls_round_trip() {
+    let json = r#"{
+        "role": "assistant",
+        "content": null,
+        "tool_calls": [{
+            "id": "call_abc123",
+            "type": "function",
+            "function": {"name": "get_weather", "arguments": "{\"city\": \"Paris\"}"}
+        }]
+    }"#;
+    let message: ChatMessage = serde_json::from_str(json).unwrap();
+
+    let calls = message.tool_calls.as_ref().unwrap();
+    assert_eq!(calls[0].id, "call_abc123");
+    assert_eq!(calls[0].tool_type, "function");
+    assert_eq!(calls[0].function.name, "get_weather");
+    assert_eq!(calls[0].function.arguments, r#"{"city": "Paris"}"#);
+
+    let value: serde_json::Value = serde_json::from_str(json).unwrap();
+    assert_eq!(serde_json::to_value(&message).unwrap(), value);
+
+    let plain = serde_json::to_value(ChatMessage {
+        tool_calls: Some(Vec::new()),
+        ..ChatMessage::assistant("Hi!")
+    })
+    .unwrap();
+    assert!(plain.get("tool_calls").is_none());
+}
+
+#[test]
+fn text_and_image_content_serialize() {
+    use serde_json::json;
+
+    let text = serde_json::to_value(ChatMessage::user("Hi!")).unwrap();
+    assert_eq!(text, json!({"role": "user", "content": "Hi!"}));
+
+    let image = ChatMessage::with_image("What's in this image?", "https://example.com/cat.png");
+    assert_eq!(
+        serde_json::to_value(&image).unwrap(),
+        json!({
+            "role": "user",
+            "content": [
+                {"type": "text", "text": "What's in this image?"},
+                {"type": "image_url", "image_url": {"url": "https://example.com/cat.png"}}
+            ]
+        })
+    );
+    assert_eq!(image.text(), None);
+    assert_eq!(
+        image.content.as_ref().unwrap().texts(),
+        ["What's in this image?"]
+    );
+
+    let parsed: ChatMessage = serde_json::from_value(json!(image)).unwrap();
+    assert_eq!(parsed.content, image.content);
+}
+
+#[tokio::test]
+async fn sampling_parameters_accept_f64() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .temperature(0.7)
+        .top_p(0.9f64)
+        .presence_penalty(0.1f32)
+        .build();
+
+    chat.send("Hi", None).await.unwrap();
+
+    let body = server.requests()[0].json();
+    assert_eq!(body["temperature"], 0.7);
+    assert_eq!(body["top_p"], 0.9);
+    assert!(body["presence_penalty"].as_f64().is_some());
+}
+
+#[tokio::test]
+async fn unset_chat_parameters_are_left_to_the_api() {
+    assert_eq!(
+        serde_json::to_value(ChatParameters::default()).unwrap(),
+        serde_json::json!({})
+    );
+
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt4, "key".to_string())
+        .base_url(server.url())
+        .build();
+
+    chat.send("Hi", None).await.unwrap();
+
+    let body = server.requests()[0].json();
+    let mut keys: Vec<_> = body.as_object().unwrap().keys().collect();
+    keys.sort();
+    assert_eq!(keys, ["messages", "model"]);
+}
+
+#[tokio::test]
+async fn clones_share_one_conversation_across_tasks() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .build();
+
+    let tasks: Vec<_> = ["one", "two"]
+        .into_iter()
+        .map(|question| {
+            let chat = chat.clone();
+            tokio::spawn(async move { chat.send(question, None).await.map(|_| ()).is_ok() })
+        })
+        .collect();
+    for task in tasks {
+        assert!(task.await.unwrap());
+    }
+
+    let messages = chat.get_messages().await;
+    assert_eq!(messages.len(), 5);
+    let mut questions: Vec<_> = [&messages[1], &messages[3]]
+        .into_iter()
+        .map(|msg| msg.text().unwrap())
+        .collect();
+    questions.sort();
+    assert_eq!(questions, ["one", "two"]);
+    assert!(matches!(messages[2].role, Role::Assistant));
+    assert!(matches!(messages[4].role, Role::Assistant));
+
+    // the second request was sent after the first exchange was added to the history
+    let requests = server.requests();
+    let sizes: Vec<_> = requests
+        .iter()
+        .map(|req| req.json()["messages"].as_array().unwrap().len())
+        .collect();
+    assert_eq!(sizes.iter().min(), Some(&2));
+    assert_eq!(sizes.iter().max(), Some(&4));
+    assert_eq!(chat.total_usage().await.total_tokens, 42);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn queued_questions_are_answered_in_the_order_they_were_asked() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .build();
+
+    chat.ask("first").await.unwrap();
+    chat.ask("second").await.unwrap();
+
+    let tasks: Vec<_> = (0..2)
+        .map(|_| {
+            let chat = chat.clone();
+            tokio::spawn(async move { chat.get_response(None).await.is_ok() })
+        })
+        .collect();
+    for task in tasks {
+        assert!(task.await.unwrap());
+    }
+
+    let messages = chat.get_messages().await;
+    assert_eq!(messages.len(), 5);
+    assert_eq!(messages[1].text(), Some("first"));
+    assert_eq!(messages[3].text(), Some("second"));
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    for request in requests {
+        let sent = request.json()["messages"].as_array().unwrap().clone();
+        let expected = if sent.len() == 2 { "first" } else { "second" };
+        assert_eq!(sent.last().unwrap()["content"], expected);
+    }
+}
+
+#[tokio::test]
+async fn full_responses_keep_usage_and_finish_reason() {
+    let response =
+        CHAT_RESPONSE.replace(r#""finish_reason": "stop""#, r#""finish_reason": "length""#);
+    let server = MockServer::start(vec![MockResponse::json(200, &response)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .build();
+
+    chat.ask("Hello!").await.unwrap();
+    let resp = chat.get_full_response(None).await.unwrap();
+
+    assert_eq!(resp.id, "chatcmpl-123");
+    assert_eq!(resp.choices[0].finish_reason, FinishReason::Length);
+    assert_eq!(resp.usage.total_tokens, 21);
+
+    let messages = chat.get_messages().await;
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[2].content, resp.choices[0].message.content);
+    assert_eq!(chat.total_usage().await.total_tokens, 21);
+}
+
+#[tokio::test]
+async fn history_is_capped_at_max_history_messages() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .max_history_messages(3)
+        .build();
+
+    for question in ["one", "two", "three", "four"] {
+        chat.ask(question).await.unwrap();
+        chat.get_response(None).await.unwrap();
+
+        // the system message and at most 3 others
+        assert!(chat.get_messages().await.len() <= 4);
+    }
+
+    let messages = chat.get_messages().await;
+    // "three"'s answer, "four" and its answer
+    assert_eq!(messages.len(), 4);
+    assert!(matches!(messages[0].role, Role::System));
+    assert!(matches!(messages[1].role, Role::Assistant));
+    assert_eq!(messages[2].text(), Some("four"));
+
+    let sizes: Vec<_> = server
+        .requests()
+        .iter()
+        .map(|req| req.json()["messages"].as_array().unwrap().len())
+        .collect();
+    assert_eq!(sizes, [2, 4, 4, 4]);
+    let last = server.requests()[3].json();
+    assert_eq!(last["messages"][1]["content"], "three");
+    assert_eq!(last["messages"][3]["content"], "four");
+}
+
+#[tokio::test]
+async fn max_history_messages_always_sends_the_new_message() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .max_history_messages(0)
+        .build();
+
+    chat.send("one", None).await.unwrap();
+    chat.send("two", None).await.unwrap();
+
+    let body = server.requests()[1].json();
+    let messages = body["messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[1]["content"], "two");
+    assert_eq!(chat.get_messages().await.len(), 2);
+}
+
+#[tokio::test]
+async fn default_user_is_sent_unless_overridden() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .user("default-user".to_string())
+        .build();
+
+    chat.send("one", None).await.unwrap();
+    chat.send("two", Some("other-user".to_string()))
+        .await
+        .unwrap();
+    chat.send("three", None).await.unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests[0].json()["user"], "default-user");
+    assert_eq!(requests[1].json()["user"], "other-user");
+    assert_eq!(requests[2].json()["user"], "default-user");
+}
+
+#[tokio::test]
+async fn per_turn_metadata_is_only_sent_on_that_turn() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .metadata(HashMap::from([
+            ("app".to_string(), "tests".to_string()),
+            ("turn".to_string(), "default".to_string()),
+        ]))
+        .store(true)
+        .build();
+
+    chat.ask("one").await.unwrap();
+    chat.get_response_with_metadata(
+        None,
+        HashMap::from([("turn".to_string(), "first".to_string())]),
+    )
+    .await
+    .unwrap();
+    chat.ask("two").await.unwrap();
+    chat.get_response(None).await.unwrap();
+
+    let requests = server.requests();
+    assert_eq!(
+        requests[0].json()["metadata"],
+        serde_json::json!({ "app": "tests", "turn": "first" })
+    );
+    assert_eq!(
+        requests[1].json()["metadata"],
+        serde_json::json!({ "app": "tests", "turn": "default" })
+    );
+    assert_eq!(requests[0].json()["store"], true);
+    assert_eq!(requests[1].json()["store"], true);
+}
+
+#[tokio::test]
+async fn max_history_messages_drops_tool_calls_with_their_results() {
+    let tool_call = r#"{
+        "id": "chatcmpl-123",
+        "created": 1677652288,
+        "choices": [{
+            "message": {
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_abc123",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}
+                }]
+            },
+            "finish_reason": "tool_calls"
+        }],
+        "usage": {"prompt_tokens": 9, "completion_tokens": 12, "total_tokens": 21}
+    }"#;
+    let server = MockServer::start(vec![
+        MockResponse::json(200, tool_call),
+        MockResponse::json(200, CHAT_RESPONSE),
+    ])
+    .await;
+    let chat = ChatBuilder::new(rust_gpt::ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .max_history_messages(3)
+        .build();
+
+    chat.send("What's the weather in Paris?", None)
+        .await
+        .unwrap();
+    chat.submit_tool_result("call_abc123".to_string(), "22C and sunny".to_string())
+        .await;
+    chat.get_response(None).await.unwrap();
+    // the tool call, its result and the answer
+    assert_eq!(chat.get_messages().await.len(), 4);
+
+    // the cap falls between the tool call and its result
+    chat.send("Thanks!", None).await.unwrap();
+
+    let body = server.requests()[2].json();
+    let roles: Vec<_> = body["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|msg| msg["role"].as_str().unwrap())
+        .collect();
+    assert_eq!(roles, ["system", "assistant", "user"]);
+
+    let messages = chat.get_messages().await;
+    assert!(messages.iter().all(|msg| !matches!(msg.role, Role::Tool)));
+    assert!(messages.len() <= 4);
+}
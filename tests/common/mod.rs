@@ -0,0 +1,220 @@
+//! A tiny HTTP server for testing requests without hitting the OpenAI API.
+#![allow(dead_code)]
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// A canned response returned by the [`MockServer`].
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub delay: Duration,
+    /// Writes the body in pieces of this size, pausing between them.
+    pub chunk_size: Option<usize>,
+}
+
+impl MockResponse {
+    pub fn json(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".into(), "application/json".into())],
+            body: body.as_bytes().to_vec(),
+            delay: Duration::ZERO,
+            chunk_size: None,
+        }
+    }
+
+    pub fn event_stream(body: &str) -> Self {
+        Self {
+            headers: vec![("Content-Type".into(), "text/event-stream".into())],
+            ..Self::json(200, body)
+        }
+    }
+
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// A request received by the [`MockServer`].
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap()
+    }
+}
+
+/// Serves the given responses in order, repeating the last one once they run out.
+pub struct MockServer {
+    url: String,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl MockServer {
+    pub async fn start(responses: Vec<MockResponse>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            let mut served = 0;
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let response = responses[served.min(responses.len() - 1)].clone();
+                served += 1;
+
+                let recorded = recorded.clone();
+                tokio::spawn(async move { handle(stream, response, recorded).await });
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    response: MockResponse,
+    recorded: Arc<Mutex<Vec<RecordedRequest>>>,
+) {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+
+    let header_end = loop {
+        let read = stream.read(&mut chunk).await.unwrap();
+        if read == 0 {
+            return;
+        }
+        buf.extend_from_slice(&chunk[..read]);
+
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let path = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .unwrap_or_default()
+        .to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(": "))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+    let content_length = headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    while buf.len() < header_end + content_length {
+        let read = stream.read(&mut chunk).await.unwrap();
+        if read == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    }
+
+    recorded.lock().unwrap().push(RecordedRequest {
+        path,
+        headers,
+        body: String::from_utf8_lossy(&buf[header_end..]).to_string(),
+    });
+
+    tokio::time::sleep(response.delay).await;
+
+    let mut head = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+
+    // the client may have given up already, e.g. when testing timeouts
+    let _ = stream.write_all(head.as_bytes()).await;
+    for piece in response
+        .body
+        .chunks(response.chunk_size.unwrap_or(response.body.len().max(1)))
+    {
+        let _ = stream.write_all(piece).await;
+        let _ = stream.flush().await;
+        if response.chunk_size.is_some() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+    let _ = stream.shutdown().await;
+}
+
+pub const CHAT_RESPONSE: &str = r#"{
+    "id": "chatcmpl-123",
+    "object": "chat.completion",
+    "created": 1677652288,
+    "choices": [{
+        "index": 0,
+        "message": {"role": "assistant", "content": "Hello there, how may I assist you today?"},
+        "finish_reason": "stop"
+    }],
+    "usage": {"prompt_tokens": 9, "completion_tokens": 12, "total_tokens": 21}
+}"#;
+
+pub const COMPLETION_RESPONSE: &str = r#"{
+    "id": "cmpl-uqkvlQyYK7bGYrRHQ0eXlWi7",
+    "object": "text_completion",
+    "created": 1589478378,
+    "model": "gpt-3.5-turbo-instruct",
+    "choices": [{
+        "text": "This is indeed a test",
+        "index": 0,
+        "logprobs": null,
+        "finish_reason": "length"
+    }],
+    "usage": {"prompt_tokens": 5, "completion_tokens": 7, "total_tokens": 12}
+}"#;
@@ -0,0 +1,51 @@
+mod common;
+
+use common::{MockResponse, MockServer};
+use rust_gpt::{embedding::EmbeddingResponse, EmbeddingModel, RequestBuilder, SendRequest};
+
+const EMBEDDING_RESPONSE: &str = r#"{
+    "object": "list",
+    "data": [
+        {"object": "embedding", "embedding": [0.0023064255, -0.009327292, -0.0028842222], "index": 0},
+        {"object": "embedding", "embedding": [0.0108, 0.0042, -0.0311], "index": 1}
+    ],
+    "model": "text-embedding-ada-002",
+    "usage": {"prompt_tokens": 8, "total_tokens": 8}
+}"#;
+
+#[test]
+fn embedding_response_deserializes() {
+    let resp: EmbeddingResponse = EMBEDDING_RESPONSE.parse().unwrap();
+
+    assert_eq!(resp.data.len(), 2);
+    assert_eq!(
+        resp.data[0].embedding,
+        [0.0023064255, -0.009327292, -0.0028842222]
+    );
+    assert_eq!(resp.data[1].index, 1);
+    assert_eq!(resp.usage.prompt_tokens, 8);
+    assert_eq!(resp.usage.completion_tokens, 0);
+}
+
+#[tokio::test]
+async fn embedding_requests_are_sent_to_the_embeddings_endpoint() {
+    let server = MockServer::start(vec![MockResponse::json(200, EMBEDDING_RESPONSE)]).await;
+
+    let resp = RequestBuilder::new(EmbeddingModel::TextEmbeddingAda002, "key")
+        .base_url(server.url())
+        .inputs(["The food was delicious", "The waiter was friendly"])
+        .build_embedding()
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.data.len(), 2);
+
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/embeddings");
+    assert_eq!(request.json()["model"], "text-embedding-ada-002");
+    assert_eq!(
+        request.json()["input"],
+        serde_json::json!(["The food was delicious", "The waiter was friendly"])
+    );
+}
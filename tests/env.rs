@@ -0,0 +1,23 @@
+use rust_gpt::{chat::ChatBuilder, BuildError, ChatModel, CompletionModel, RequestBuilder};
+
+// the environment is shared by the whole process, so everything touching it lives in this one test
+#[test]
+fn api_key_is_read_from_the_environment() {
+    std::env::remove_var("OPENAI_API_KEY");
+    assert_eq!(
+        RequestBuilder::<rust_gpt::CompletionState>::from_env(CompletionModel::Gpt35TurboInstruct)
+            .err()
+            .unwrap(),
+        BuildError::MissingApiKey
+    );
+
+    std::env::set_var("OPENAI_API_KEY", " ");
+    assert_eq!(
+        ChatBuilder::from_env(ChatModel::Gpt35Turbo).err().unwrap(),
+        BuildError::MissingApiKey
+    );
+
+    std::env::set_var("OPENAI_API_KEY", "sk-abc");
+    assert!(ChatBuilder::from_env(ChatModel::Gpt35Turbo).is_ok());
+    assert!(RequestBuilder::<rust_gpt::ChatState>::from_env(ChatModel::Gpt35Turbo).is_ok());
+}
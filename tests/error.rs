@@ -0,0 +1,42 @@
+use rust_gpt::{OpenAiApiError, SendRequestError};
+
+#[test]
+fn insufficient_quota_is_detected() {
+    let err = SendRequestError::OpenAiError(OpenAiApiError::from_body(
+        r#"{"error": {"message": "You exceeded your current quota.", "type": "insufficient_quota", "param": null, "code": "insufficient_quota"}}"#,
+    ));
+
+    assert!(err.is_insufficient_quota());
+}
+
+#[test]
+fn rate_limit_is_not_insufficient_quota() {
+    let err = SendRequestError::OpenAiError(OpenAiApiError::from_body(
+        r#"{"error": {"message": "Rate limit reached.", "type": "requests", "param": null, "code": "rate_limit_exceeded"}}"#,
+    ));
+
+    assert!(!err.is_insufficient_quota());
+    assert!(
+        !SendRequestError::OpenAiError(OpenAiApiError::from_body("not json"))
+            .is_insufficient_quota()
+    );
+}
+
+#[test]
+fn api_errors_are_deserialized() {
+    let err = OpenAiApiError::from_body(
+        r#"{"error": {"message": "This model's maximum context length is 4097 tokens.", "type": "invalid_request_error", "param": "messages", "code": "context_length_exceeded"}}"#,
+    );
+
+    assert_eq!(err.error_type, "invalid_request_error");
+    assert_eq!(err.param.as_deref(), Some("messages"));
+    assert_eq!(err.code.as_deref(), Some("context_length_exceeded"));
+}
+
+#[test]
+fn unexpected_error_bodies_keep_the_raw_text() {
+    let err = OpenAiApiError::from_body("<html>Bad Gateway</html>");
+
+    assert_eq!(err.message, "<html>Bad Gateway</html>");
+    assert_eq!(err.code, None);
+}
@@ -9,11 +9,13 @@ fn example_chat_request() {
     .messages(vec![
         ChatMessage {
             role: Role::System,
-            content: "You are a helpful assistant.".to_string().into(),
+            content: Some("You are a helpful assistant.".into()),
+            ..Default::default()
         },
         ChatMessage {
             role: Role::User,
-            content: "Who started World War 2?".to_string().into(),
+            content: Some("Who started World War 2?".into()),
+            ..Default::default()
         },
     ])
     .max_tokens(128)
@@ -32,7 +34,7 @@ fn example_chat_request() {
 #[test]
 fn example_completion_request() {
     let req = RequestBuilder::new(
-        CompletionModel::TextDavinci003,
+        CompletionModel::Gpt35TurboInstruct,
         std::env::var("OPENAI_API_KEY").unwrap(),
     )
     .prompt("Once upon a time, there was a")
@@ -58,9 +60,8 @@ fn chat_experimental_test() {
     .max_tokens(128)
     .system(ChatMessage {
         role: Role::System,
-        content: "You are a dog with an incredible amount of trivia knowledge"
-            .to_string()
-            .into(),
+        content: Some("You are a dog with an incredible amount of trivia knowledge".into()),
+        ..Default::default()
     })
     .build();
 
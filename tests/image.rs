@@ -0,0 +1,57 @@
+mod common;
+
+use common::{MockResponse, MockServer};
+use rust_gpt::{
+    image::{ImageResponse, ImageResponseFormat, Size},
+    ImageModel, RequestBuilder, SendRequest,
+};
+
+const IMAGE_RESPONSE: &str = r#"{
+    "created": 1589478378,
+    "data": [
+        {"url": "https://example.com/otter.png", "revised_prompt": "A cute baby sea otter floating on its back"},
+        {"b64_json": "iVBORw0KGgo="}
+    ]
+}"#;
+
+#[test]
+fn image_response_deserializes() {
+    let resp: ImageResponse = IMAGE_RESPONSE.parse().unwrap();
+
+    assert_eq!(resp.created, 1589478378);
+    assert_eq!(
+        resp.data[0].url.as_deref(),
+        Some("https://example.com/otter.png")
+    );
+    assert!(resp.data[0].b64_json.is_none());
+    assert!(resp.data[0].revised_prompt.is_some());
+    assert_eq!(resp.data[1].b64_json.as_deref(), Some("iVBORw0KGgo="));
+    assert!(resp.data[1].url.is_none());
+}
+
+#[tokio::test]
+async fn image_requests_are_sent_to_the_generations_endpoint() {
+    let server = MockServer::start(vec![MockResponse::json(200, IMAGE_RESPONSE)]).await;
+
+    let resp = RequestBuilder::new(ImageModel::DallE2, "key")
+        .base_url(server.url())
+        .size(Size::S512)
+        .response_format(ImageResponseFormat::B64Json)
+        .prompt("A cute baby sea otter")
+        .n(2)
+        .build_image()
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.data.len(), 2);
+
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/images/generations");
+    let body = request.json();
+    assert_eq!(body["model"], "dall-e-2");
+    assert_eq!(body["prompt"], "A cute baby sea otter");
+    assert_eq!(body["n"], 2);
+    assert_eq!(body["size"], "512x512");
+    assert_eq!(body["response_format"], "b64_json");
+}
@@ -0,0 +1,86 @@
+use rust_gpt::{chat::Role, ChatModel, CompletionModel};
+
+#[test]
+fn completion_model_strings() {
+    assert_eq!(
+        CompletionModel::Gpt35TurboInstruct.to_string(),
+        "gpt-3.5-turbo-instruct"
+    );
+}
+
+#[test]
+fn gpt4_model_strings() {
+    assert_eq!(ChatModel::Gpt4.to_string(), "gpt-4");
+    assert_eq!(ChatModel::Gpt40314.to_string(), "gpt-4-0314");
+    assert_eq!(ChatModel::Gpt4_32k.to_string(), "gpt-4-32k");
+    assert_eq!(ChatModel::Gpt4_32k0314.to_string(), "gpt-4-32k-0314");
+}
+
+#[test]
+fn custom_model_strings() {
+    assert_eq!(ChatModel::Custom("gpt-4o".into()).to_string(), "gpt-4o");
+    assert_eq!(
+        CompletionModel::Custom("babbage-002".into()).to_string(),
+        "babbage-002"
+    );
+}
+
+#[test]
+fn models_and_roles_parse_from_strings() {
+    let model: ChatModel = "gpt-4-32k".parse().unwrap();
+    assert_eq!(model.to_string(), "gpt-4-32k");
+    let model: CompletionModel = "gpt-3.5-turbo-instruct".parse().unwrap();
+    assert_eq!(model.to_string(), "gpt-3.5-turbo-instruct");
+    let role: Role = "assistant".parse().unwrap();
+    assert_eq!(role.to_string(), "assistant");
+
+    let err = "gpt-5".parse::<ChatModel>().unwrap_err();
+    assert_eq!(err.to_string(), r#"unknown chat model: "gpt-5""#);
+    assert!("gpt-4".parse::<CompletionModel>().is_err());
+    assert_eq!(
+        "robot".parse::<Role>().unwrap_err().to_string(),
+        r#"unknown role: "robot""#
+    );
+}
+
+#[test]
+fn chat_model_context_windows() {
+    assert_eq!(ChatModel::Gpt35Turbo.context_window(), 4096);
+    assert_eq!(ChatModel::Gpt35Turbo0301.context_window(), 4096);
+    assert_eq!(ChatModel::Gpt35Turbo16k.context_window(), 16384);
+    assert_eq!(ChatModel::Gpt4.context_window(), 8192);
+    assert_eq!(ChatModel::Gpt40314.context_window(), 8192);
+    assert_eq!(ChatModel::Gpt4_32k.context_window(), 32768);
+    assert_eq!(ChatModel::Gpt4_32k0314.context_window(), 32768);
+    assert_eq!(
+        ChatModel::Custom("gpt-4-32k".into()).context_window(),
+        32768
+    );
+    assert_eq!(
+        ChatModel::Custom("my-fine-tune".into()).context_window(),
+        4096
+    );
+}
+
+#[test]
+#[allow(deprecated)]
+fn completion_model_context_windows() {
+    assert_eq!(CompletionModel::Gpt35TurboInstruct.context_window(), 4096);
+    assert_eq!(CompletionModel::TextDavinci003.context_window(), 4097);
+    assert_eq!(CompletionModel::CodeDavinci002.context_window(), 8001);
+    assert_eq!(
+        CompletionModel::Custom("babbage-002".into()).context_window(),
+        16384
+    );
+    assert_eq!(CompletionModel::Custom("ada".into()).context_window(), 4096);
+}
+
+#[test]
+fn max_output_tokens_is_what_is_left_of_the_window() {
+    assert_eq!(ChatModel::Gpt4.max_output_tokens(1000), 7192);
+    assert_eq!(ChatModel::Gpt4.max_output_tokens(10000), 0);
+    assert_eq!(
+        CompletionModel::Gpt35TurboInstruct.max_output_tokens(96),
+        4000
+    );
+}
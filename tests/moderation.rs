@@ -0,0 +1,60 @@
+mod common;
+
+use common::{MockResponse, MockServer};
+use rust_gpt::{moderation::ModerationResponse, ModerationModel, RequestBuilder, SendRequest};
+
+const MODERATION_RESPONSE: &str = r#"{
+    "id": "modr-XXXXX",
+    "model": "text-moderation-007",
+    "results": [{
+        "flagged": true,
+        "categories": {
+            "sexual": false,
+            "hate": false,
+            "harassment": false,
+            "self-harm": false,
+            "self-harm/intent": false,
+            "violence": true,
+            "violence/graphic": false
+        },
+        "category_scores": {
+            "sexual": 1.2282071e-06,
+            "hate": 0.010696256,
+            "harassment": 0.29842457,
+            "self-harm": 1.5236925e-08,
+            "self-harm/intent": 5.7246268e-08,
+            "violence": 0.99011886,
+            "violence/graphic": 4.2470037e-08
+        }
+    }]
+}"#;
+
+#[test]
+fn moderation_response_deserializes() {
+    let resp: ModerationResponse = MODERATION_RESPONSE.parse().unwrap();
+
+    assert!(resp.flagged());
+    let result = &resp.results[0];
+    assert!(result.categories["violence"]);
+    assert!(!result.categories["self-harm/intent"]);
+    assert!(result.category_scores["violence"] > 0.99);
+}
+
+#[tokio::test]
+async fn moderation_requests_are_sent_to_the_moderations_endpoint() {
+    let server = MockServer::start(vec![MockResponse::json(200, MODERATION_RESPONSE)]).await;
+
+    let resp = RequestBuilder::new(ModerationModel::TextModerationLatest, "key")
+        .base_url(server.url())
+        .input("I want to hurt them.")
+        .build_moderation()
+        .send()
+        .await
+        .unwrap();
+
+    assert!(resp.flagged());
+
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/moderations");
+    assert_eq!(request.json()["input"], "I want to hurt them.");
+}
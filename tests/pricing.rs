@@ -0,0 +1,57 @@
+use rust_gpt::{
+    chat::Usage,
+    pricing::{Pricing, PricingTable},
+};
+
+fn usage(prompt_tokens: u32, completion_tokens: u32) -> Usage {
+    Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+    }
+}
+
+fn assert_close(actual: Option<f64>, expected: f64) {
+    let actual = actual.unwrap();
+    assert!(
+        (actual - expected).abs() < 1e-12,
+        "{actual} is not {expected}"
+    );
+}
+
+#[test]
+fn known_models_are_priced() {
+    assert_close(usage(2000, 1000).estimated_cost("gpt-4"), 0.12);
+    assert_close(usage(2000, 1000).estimated_cost("gpt-3.5-turbo"), 0.005);
+    assert_close(
+        usage(1000, 0).estimated_cost("text-embedding-ada-002"),
+        0.0001,
+    );
+}
+
+#[test]
+fn snapshots_use_the_price_of_their_model() {
+    let usage = usage(1000, 1000);
+
+    assert_eq!(
+        usage.estimated_cost("gpt-4-0613"),
+        usage.estimated_cost("gpt-4")
+    );
+    assert_eq!(
+        usage.estimated_cost("gpt-3.5-turbo-16k-0613"),
+        usage.estimated_cost("gpt-3.5-turbo-16k")
+    );
+    assert_eq!(usage.estimated_cost("gpt-4-turbo"), None);
+    assert_eq!(usage.estimated_cost("my-fine-tune"), None);
+}
+
+#[test]
+fn prices_can_be_overridden() {
+    let table = PricingTable::default()
+        .set("gpt-4", Pricing::new(0.01, 0.03))
+        .set("my-fine-tune", Pricing::new(0.012, 0.016));
+
+    assert_close(table.cost("gpt-4", &usage(1000, 1000)), 0.04);
+    assert_close(table.cost("my-fine-tune", &usage(1000, 500)), 0.02);
+    assert_eq!(PricingTable::empty().cost("gpt-4", &usage(1, 1)), None);
+}
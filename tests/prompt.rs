@@ -0,0 +1,21 @@
+use rust_gpt::prompt::Prompt;
+
+#[test]
+fn fills_placeholders() {
+    let prompt = Prompt::template("Summarize {text} in {n} words");
+
+    assert_eq!(
+        prompt.fill([("text", "the article"), ("n", "3")]),
+        "Summarize the article in 3 words"
+    );
+}
+
+#[test]
+fn escaped_and_unknown_braces_are_kept() {
+    let prompt = Prompt::template("Return {{\"name\": \"{name}\"}} for {unknown} {");
+
+    assert_eq!(
+        prompt.fill([("name", "Ferris")]),
+        "Return {\"name\": \"Ferris\"} for {unknown} {"
+    );
+}
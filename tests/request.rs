@@ -0,0 +1,903 @@
+mod common;
+
+use common::{MockResponse, MockServer, CHAT_RESPONSE, COMPLETION_RESPONSE};
+use rust_gpt::{chat::ChatMessage, *};
+
+#[test]
+fn consistent_parameters_build() {
+    assert!(
+        RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+            .prompt("Say hi")
+            .n(2)
+            .stream(true)
+            .try_build_completion()
+            .is_ok()
+    );
+
+    assert!(RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .messages(vec![ChatMessage::default()])
+        .try_build_chat()
+        .is_ok());
+}
+
+#[tokio::test]
+async fn fresh_clients_reach_separate_mock_servers() {
+    let first = MockServer::start(vec![MockResponse::json(200, COMPLETION_RESPONSE)]).await;
+    let second = MockServer::start(vec![MockResponse::json(200, COMPLETION_RESPONSE)]).await;
+
+    for server in [&first, &second] {
+        RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+            .base_url(server.url())
+            .prompt("Say hi")
+            .build_completion()
+            .with_fresh_client()
+            .send()
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(first.requests().len(), 1);
+    assert_eq!(second.requests().len(), 1);
+    assert_eq!(first.requests()[0].path, "/completions");
+}
+
+#[test]
+fn message_length_check_names_the_offending_message() {
+    let messages = || {
+        vec![
+            ChatMessage::default(),
+            ChatMessage {
+                content: Some("word ".repeat(20_000).into()),
+                ..Default::default()
+            },
+        ]
+    };
+
+    assert!(RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .messages(messages())
+        .try_build_chat()
+        .is_ok());
+
+    let err = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .messages(messages())
+        .check_message_lengths(true)
+        .try_build_chat()
+        .err()
+        .unwrap();
+
+    assert!(
+        matches!(err, BuildError::MessageTooLong { index: 1, .. }),
+        "{err}"
+    );
+}
+
+#[test]
+fn tool_choice_requires_tools() {
+    let err = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .messages(vec![ChatMessage::default()])
+        .tool_choice(chat::ToolChoice::Auto)
+        .try_build_chat()
+        .err()
+        .unwrap();
+
+    assert_eq!(err, BuildError::ToolChoiceWithoutTools);
+}
+
+#[test]
+fn tools_and_tool_choice_build_together() {
+    let weather = chat::FunctionDef {
+        name: "get_weather".to_string(),
+        description: "Gets the weather in a city".to_string(),
+        parameters: serde_json::json!({
+            "type": "object",
+            "properties": {"city": {"type": "string"}},
+        }),
+    };
+
+    let req = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .messages(vec![ChatMessage::user("What's the weather in Paris?")])
+        .tools(vec![chat::Tool::function(weather)])
+        .tool_choice(chat::ToolChoice::Function("get_weather".to_string()))
+        .try_build_chat()
+        .unwrap();
+
+    let body: serde_json::Value = serde_json::from_str(req.body()).unwrap();
+    assert_eq!(body["tools"][0]["type"], "function");
+    assert_eq!(body["tools"][0]["function"]["name"], "get_weather");
+    assert_eq!(body["tool_choice"]["function"]["name"], "get_weather");
+}
+
+#[tokio::test]
+async fn gzipped_responses_are_decoded() {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(COMPLETION_RESPONSE.as_bytes()).unwrap();
+
+    let mut response = MockResponse::json(200, "").header("Content-Encoding", "gzip");
+    response.body = encoder.finish().unwrap();
+    let server = MockServer::start(vec![response]).await;
+
+    let resp = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .base_url(server.url())
+        .prompt("Say hi")
+        .build_completion()
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.choices[0].text, "This is indeed a test");
+    assert!(server.requests()[0]
+        .header("accept-encoding")
+        .unwrap()
+        .contains("gzip"));
+}
+
+#[tokio::test]
+async fn custom_models_are_sent_verbatim() {
+    let server = MockServer::start(vec![MockResponse::json(200, common::CHAT_RESPONSE)]).await;
+
+    RequestBuilder::new(ChatModel::Custom("gpt-3.5-turbo-1106".into()), "key")
+        .base_url(server.url())
+        .messages(vec![ChatMessage::default()])
+        .build_chat()
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(server.requests()[0].json()["model"], "gpt-3.5-turbo-1106");
+}
+
+#[tokio::test]
+async fn user_agent_defaults_to_crate_version_and_can_be_overridden() {
+    let server = MockServer::start(vec![MockResponse::json(200, COMPLETION_RESPONSE)]).await;
+
+    let builder = || {
+        RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+            .base_url(server.url())
+            .prompt("Say hi")
+    };
+
+    builder().build_completion().send().await.unwrap();
+    builder()
+        .user_agent("my-app/1.0")
+        .build_completion()
+        .send()
+        .await
+        .unwrap();
+
+    let requests = server.requests();
+    assert_eq!(
+        requests[0].header("user-agent"),
+        Some(concat!("rust-gpt/", env!("CARGO_PKG_VERSION")))
+    );
+    assert_eq!(requests[1].header("user-agent"), Some("my-app/1.0"));
+}
+
+#[tokio::test]
+async fn error_statuses_are_errors_not_panics() {
+    let mut html = MockResponse::json(500, "<html><body>Internal Server Error</body></html>");
+    html.headers = vec![("Content-Type".into(), "text/html".into())];
+    let server = MockServer::start(vec![html]).await;
+
+    let completion = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .base_url(server.url())
+        .prompt("Say hi")
+        .build_completion()
+        .send()
+        .await;
+    let chat = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .base_url(server.url())
+        .messages(vec![ChatMessage::default()])
+        .build_chat()
+        .send()
+        .await;
+
+    for err in [completion.err().unwrap(), chat.err().unwrap()] {
+        let message = err.to_string();
+        assert!(message.contains("500"), "{message}");
+        assert!(message.contains("Internal Server Error"), "{message}");
+    }
+}
+
+#[tokio::test]
+async fn quota_errors_keep_their_code() {
+    let server = MockServer::start(vec![MockResponse::json(
+        429,
+        r#"{"error": {"message": "You exceeded your current quota.", "type": "insufficient_quota", "param": null, "code": "insufficient_quota"}}"#,
+    )])
+    .await;
+
+    let err = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .base_url(server.url())
+        .messages(vec![ChatMessage::default()])
+        .build_chat()
+        .send()
+        .await
+        .err()
+        .unwrap();
+
+    assert!(err.is_insufficient_quota());
+}
+
+#[tokio::test]
+async fn responses_without_choices_are_reported_as_such() {
+    let server = MockServer::start(vec![MockResponse::json(
+        200,
+        r#"{"id": "chatcmpl-123", "object": "chat.completion"}"#,
+    )])
+    .await;
+
+    let err = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .base_url(server.url())
+        .messages(vec![ChatMessage::default()])
+        .build_chat()
+        .send()
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, SendRequestError::MissingChoices(_)));
+    assert!(std::error::Error::source(&err).is_some());
+    assert!(err.to_string().contains("chatcmpl-123"));
+}
+
+#[tokio::test]
+async fn send_with_timeout_gives_up_on_slow_servers() {
+    let server =
+        MockServer::start(vec![MockResponse::json(200, COMPLETION_RESPONSE)
+            .delay(std::time::Duration::from_millis(500))])
+        .await;
+
+    let err = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .base_url(server.url())
+        .prompt("Say hi")
+        .build_completion()
+        .send_with_timeout(std::time::Duration::from_millis(50))
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, SendRequestError::Timeout(_)));
+}
+
+#[tokio::test]
+async fn non_json_bodies_are_json_errors() {
+    for body in ["", "<html><body>Bad Gateway</body></html>"] {
+        let server = MockServer::start(vec![MockResponse::json(200, body)]).await;
+
+        let err = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+            .base_url(server.url())
+            .prompt("Say hi")
+            .build_completion()
+            .with_fresh_client()
+            .send()
+            .await
+            .unwrap_err();
+
+        let source = std::error::Error::source(&err)
+            .and_then(std::error::Error::source)
+            .expect("the serde error should be the root cause");
+        assert!(source.is::<serde_json::Error>());
+
+        match err {
+            SendRequestError::JsonError(e) => {
+                assert!(e.to_string().ends_with(body));
+                assert_eq!(e.json_string(), body);
+            }
+            other => panic!("expected a json error, got {other}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn requests_are_logged_to_jsonl() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let path = std::env::temp_dir().join(format!("rust-gpt-log-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    for _ in 0..2 {
+        RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+            .base_url(server.url())
+            .log_to(&path)
+            .messages(vec![ChatMessage::default()])
+            .build_chat()
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let lines: Vec<serde_json::Value> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["request"]["model"], "gpt-3.5-turbo");
+    assert_eq!(lines[0]["response"]["id"], "chatcmpl-123");
+    assert_eq!(lines[0]["usage"]["total_tokens"], 21);
+    assert!(lines[0]["timestamp"].is_u64());
+}
+
+#[tokio::test]
+async fn custom_clients_are_used() {
+    let server = MockServer::start(vec![MockResponse::json(200, COMPLETION_RESPONSE)]).await;
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("X-Custom", "yes".parse().unwrap());
+    let client = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap();
+
+    RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .base_url(server.url())
+        .client(client)
+        .prompt("Say hi")
+        .build_completion()
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(server.requests()[0].header("X-Custom"), Some("yes"));
+}
+
+#[tokio::test]
+async fn organization_header_is_only_sent_when_set() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+
+    let builder = || {
+        RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+            .base_url(server.url())
+            .messages(vec![ChatMessage::default()])
+    };
+
+    builder().build_chat().send().await.unwrap();
+    builder()
+        .organization("org-123")
+        .build_chat()
+        .send()
+        .await
+        .unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests[0].header("openai-organization"), None);
+    assert_eq!(requests[1].header("openai-organization"), Some("org-123"));
+}
+
+#[tokio::test]
+async fn extra_headers_are_sent_and_replace_default_ones() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+
+    RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .base_url(server.url())
+        .messages(vec![ChatMessage::default()])
+        .header("OpenAI-Beta", "assistants=v1")
+        .header("openai-beta", "assistants=v2")
+        .header("user-agent", "my-gateway/1.0")
+        .build_chat()
+        .send()
+        .await
+        .unwrap();
+
+    let request = &server.requests()[0];
+    let values = |name: &str| {
+        request
+            .headers
+            .iter()
+            .filter(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(values("openai-beta"), ["assistants=v2"]);
+    assert_eq!(values("user-agent"), ["my-gateway/1.0"]);
+    assert_eq!(request.header("authorization"), Some("Bearer key"));
+}
+
+#[tokio::test]
+async fn stop_sequences_are_sent_as_an_array_and_limited_to_four() {
+    let server = MockServer::start(vec![MockResponse::json(200, COMPLETION_RESPONSE)]).await;
+    let builder = || {
+        RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+            .base_url(server.url())
+            .prompt("Say hi")
+    };
+
+    builder()
+        .stop_sequences(["\n\n", "User:"])
+        .try_build_completion()
+        .unwrap()
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        server.requests()[0].json()["stop"],
+        serde_json::json!(["\n\n", "User:"])
+    );
+
+    let err = builder()
+        .stop_sequences(["a", "b", "c", "d", "e"])
+        .try_build_completion()
+        .err()
+        .unwrap();
+    assert_eq!(err, BuildError::TooManyStopSequences(5));
+}
+
+#[tokio::test]
+async fn logprobs_are_sent_and_limited_to_five() {
+    let server = MockServer::start(vec![MockResponse::json(200, COMPLETION_RESPONSE)]).await;
+    let builder = || {
+        RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+            .base_url(server.url())
+            .prompt("Say hi")
+    };
+
+    builder()
+        .logprobs(5)
+        .try_build_completion()
+        .unwrap()
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(server.requests()[0].json()["logprobs"], 5);
+
+    let err = builder().logprobs(6).try_build_completion().err().unwrap();
+    assert_eq!(err, BuildError::LogprobsTooHigh(6));
+
+    // 300 would wrap around to 44 if it were truncated to a u8
+    let err = builder()
+        .raw_field("logprobs", serde_json::json!(300))
+        .try_build_completion()
+        .err()
+        .unwrap();
+    assert_eq!(
+        err,
+        BuildError::OutOfRange {
+            parameter: "logprobs",
+            value: 300.0,
+            min: 0.0,
+            max: 5.0,
+        }
+    );
+}
+
+#[tokio::test]
+async fn rate_limits_and_server_errors_are_retried() {
+    let server = MockServer::start(vec![
+        MockResponse::json(500, r#"{"error": {"message": "Server error", "type": "server_error", "param": null, "code": null}}"#),
+        MockResponse::json(429, r#"{"error": {"message": "Rate limit reached.", "type": "requests", "param": null, "code": "rate_limit_exceeded"}}"#)
+            .header("Retry-After", "0"),
+        MockResponse::json(200, COMPLETION_RESPONSE),
+    ])
+    .await;
+
+    let resp = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .base_url(server.url())
+        .retry(RetryConfig {
+            max_retries: 2,
+            base_delay: std::time::Duration::from_millis(1),
+        })
+        .prompt("Say hi")
+        .build_completion()
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.choices[0].text, "This is indeed a test");
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn insufficient_quota_is_not_retried() {
+    let server = MockServer::start(vec![MockResponse::json(
+        429,
+        r#"{"error": {"message": "You exceeded your current quota.", "type": "insufficient_quota", "param": null, "code": "insufficient_quota"}}"#,
+    )])
+    .await;
+
+    let err = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .base_url(server.url())
+        .retry(RetryConfig {
+            max_retries: 3,
+            base_delay: std::time::Duration::from_millis(1),
+        })
+        .prompt("Say hi")
+        .build_completion()
+        .send()
+        .await
+        .unwrap_err();
+
+    assert!(err.is_insufficient_quota());
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn timeouts_are_reqwest_errors() {
+    let server =
+        MockServer::start(vec![MockResponse::json(200, COMPLETION_RESPONSE)
+            .delay(std::time::Duration::from_millis(500))])
+        .await;
+
+    let err = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .base_url(server.url())
+        .timeout(std::time::Duration::from_millis(1))
+        .prompt("Say hi")
+        .build_completion()
+        .send()
+        .await
+        .unwrap_err();
+
+    match err {
+        SendRequestError::ReqwestError(e) => assert!(e.is_timeout()),
+        other => panic!("expected a reqwest error, got {other}"),
+    }
+}
+
+#[tokio::test]
+async fn bearer_prefix_is_not_doubled() {
+    let server = MockServer::start(vec![MockResponse::json(200, COMPLETION_RESPONSE)]).await;
+
+    for key in ["sk-abc", "Bearer sk-abc", " bearer  sk-abc "] {
+        RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, key)
+            .base_url(server.url())
+            .prompt("Say hi")
+            .build_completion()
+            .send()
+            .await
+            .unwrap();
+    }
+
+    for request in server.requests() {
+        assert_eq!(request.header("authorization"), Some("Bearer sk-abc"));
+    }
+}
+
+#[tokio::test]
+async fn prompts_are_sent_as_an_array() {
+    let server = MockServer::start(vec![MockResponse::json(200, COMPLETION_RESPONSE)]).await;
+
+    RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .base_url(server.url())
+        .prompts(vec!["Say hi".to_string(), "Say bye".to_string()])
+        .build_completion()
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(
+        server.requests()[0].json()["prompt"],
+        serde_json::json!(["Say hi", "Say bye"])
+    );
+}
+
+#[test]
+fn prompts_accept_slices_of_str() {
+    let req = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .prompts(&["a", "b"])
+        .build_completion();
+
+    let body: serde_json::Value = serde_json::from_str(req.body()).unwrap();
+    assert_eq!(body["prompt"], serde_json::json!(["a", "b"]));
+}
+
+#[tokio::test]
+async fn logit_bias_is_sent_as_an_object_and_range_checked() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+    let builder = || {
+        RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+            .base_url(server.url())
+            .messages(vec![ChatMessage::default()])
+    };
+
+    builder()
+        .logit_bias(std::collections::HashMap::from([(50256, -100.0)]))
+        .try_build_chat()
+        .unwrap()
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        server.requests()[0].json()["logit_bias"],
+        serde_json::json!({"50256": -100.0})
+    );
+
+    let err = builder()
+        .logit_bias(std::collections::HashMap::from([(50256, 150.0)]))
+        .try_build_chat()
+        .err()
+        .unwrap();
+    assert_eq!(
+        err,
+        BuildError::LogitBiasOutOfRange {
+            token: 50256,
+            bias: 150.0
+        }
+    );
+}
+
+#[tokio::test]
+async fn best_of_and_echo_are_sent() {
+    let server = MockServer::start(vec![MockResponse::json(200, COMPLETION_RESPONSE)]).await;
+
+    RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .base_url(server.url())
+        .prompt("Say hi")
+        .n(2)
+        .best_of(3)
+        .echo(true)
+        .try_build_completion()
+        .unwrap()
+        .send()
+        .await
+        .unwrap();
+
+    let body = server.requests()[0].json();
+    assert_eq!(body["best_of"], 3);
+    assert_eq!(body["echo"], true);
+
+    let err = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .prompt("Say hi")
+        .n(2)
+        .best_of(1)
+        .try_build_completion()
+        .err()
+        .unwrap();
+    assert_eq!(err, BuildError::BestOfLessThanN { best_of: 1, n: 2 });
+}
+
+#[tokio::test]
+async fn suffix_is_only_sent_when_set() {
+    let server = MockServer::start(vec![MockResponse::json(200, COMPLETION_RESPONSE)]).await;
+    let builder = || {
+        RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+            .base_url(server.url())
+            .prompt("fn add(a: i32, b: i32) -> i32 {")
+    };
+
+    builder().build_completion().send().await.unwrap();
+    builder()
+        .suffix("}")
+        .build_completion()
+        .send()
+        .await
+        .unwrap();
+
+    let requests = server.requests();
+    assert!(requests[0].json().get("suffix").is_none());
+    assert_eq!(requests[1].json()["suffix"], "}");
+}
+
+#[test]
+fn sampling_parameters_are_range_checked() {
+    let builder =
+        || RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key").prompt("Say hi");
+    let out_of_range = |parameter, value, min, max| BuildError::OutOfRange {
+        parameter,
+        value,
+        min,
+        max,
+    };
+
+    assert!(builder()
+        .temperature(0.0)
+        .top_p(1.0)
+        .presence_penalty(-2.0)
+        .frequency_penalty(2.0)
+        .try_build_completion()
+        .is_ok());
+    assert!(builder()
+        .temperature(2.0)
+        .top_p(0.0)
+        .try_build_completion()
+        .is_ok());
+
+    let err = |builder: RequestBuilder<_>| builder.try_build_completion().err().unwrap();
+    assert_eq!(
+        err(builder().temperature(2.5)),
+        out_of_range("temperature", 2.5, 0.0, 2.0)
+    );
+    assert_eq!(
+        err(builder().temperature(-0.5)),
+        out_of_range("temperature", -0.5, 0.0, 2.0)
+    );
+    assert_eq!(
+        err(builder().top_p(1.5)),
+        out_of_range("top_p", 1.5, 0.0, 1.0)
+    );
+    assert_eq!(
+        err(builder().presence_penalty(-2.5)),
+        out_of_range("presence_penalty", -2.5, -2.0, 2.0)
+    );
+    assert_eq!(
+        err(builder().frequency_penalty(2.5)),
+        out_of_range("frequency_penalty", 2.5, -2.0, 2.0)
+    );
+}
+
+#[test]
+fn sampling_parameters_keep_f64_precision() {
+    let config: serde_json::Value = serde_json::json!({"temperature": 0.7, "top_p": 0.9});
+    let temperature = config["temperature"].as_f64().unwrap();
+
+    let req = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .prompt("Say hi")
+        .temperature(temperature)
+        .top_p(config["top_p"].as_f64().unwrap())
+        .presence_penalty(0.1)
+        .frequency_penalty(0.5f32)
+        .try_build_completion()
+        .unwrap();
+
+    let body: serde_json::Value = serde_json::from_str(req.body()).unwrap();
+    assert_eq!(body["temperature"], 0.7);
+    assert_eq!(body["top_p"], 0.9);
+    assert_eq!(body["presence_penalty"], 0.1);
+    assert_eq!(body["frequency_penalty"], 0.5);
+}
+
+#[test]
+fn no_max_tokens_removes_max_tokens_from_the_body() {
+    let req = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .messages(vec![ChatMessage::user("Hello")])
+        .max_tokens(100)
+        .no_max_tokens()
+        .build_chat();
+
+    let body: serde_json::Value = serde_json::from_str(req.body()).unwrap();
+    assert!(body.get("max_tokens").is_none());
+    assert!(!req.body().contains("max_tokens"));
+}
+
+#[test]
+fn metadata_and_store_are_sent() {
+    let req = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .messages(vec![ChatMessage::user("Hello")])
+        .metadata(std::collections::HashMap::from([(
+            "app".to_string(),
+            "tests".to_string(),
+        )]))
+        .store(true)
+        .build_chat();
+
+    let body: serde_json::Value = serde_json::from_str(req.body()).unwrap();
+    assert_eq!(body["metadata"], serde_json::json!({ "app": "tests" }));
+    assert_eq!(body["store"], true);
+}
+
+#[tokio::test]
+async fn detailed_responses_carry_status_request_id_and_rate_limits() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)
+        .header("x-request-id", "req-123")
+        .header("x-ratelimit-limit-requests", "3500")
+        .header("x-ratelimit-limit-tokens", "90000")
+        .header("x-ratelimit-remaining-requests", "3499")
+        .header("x-ratelimit-remaining-tokens", "89970")
+        .header("x-ratelimit-reset-requests", "17ms")
+        .header("x-ratelimit-reset-tokens", "20ms")])
+    .await;
+
+    let resp = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .base_url(server.url())
+        .messages(vec![ChatMessage::user("Hello")])
+        .build_chat()
+        .send_detailed()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status, reqwest::StatusCode::OK);
+    assert_eq!(resp.request_id.as_deref(), Some("req-123"));
+    assert_eq!(resp.rate_limit.limit_requests, Some(3500));
+    assert_eq!(resp.rate_limit.limit_tokens, Some(90000));
+    assert_eq!(resp.rate_limit.remaining_requests, Some(3499));
+    assert_eq!(resp.rate_limit.remaining_tokens, Some(89970));
+    assert_eq!(resp.rate_limit.reset_requests.as_deref(), Some("17ms"));
+    assert_eq!(resp.rate_limit.reset_tokens.as_deref(), Some("20ms"));
+    assert_eq!(resp.raw_body, CHAT_RESPONSE);
+    assert_eq!(resp.body.id, "chatcmpl-123");
+}
+
+#[tokio::test]
+async fn missing_rate_limit_headers_are_none() {
+    let server = MockServer::start(vec![MockResponse::json(200, CHAT_RESPONSE)]).await;
+
+    let resp = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .base_url(server.url())
+        .messages(vec![ChatMessage::user("Hello")])
+        .build_chat()
+        .send_detailed()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status, reqwest::StatusCode::OK);
+    assert_eq!(resp.request_id, None);
+    assert_eq!(resp.rate_limit.limit_requests, None);
+    assert_eq!(resp.rate_limit.limit_tokens, None);
+    assert_eq!(resp.rate_limit.remaining_requests, None);
+    assert_eq!(resp.rate_limit.remaining_tokens, None);
+    assert_eq!(resp.rate_limit.reset_requests, None);
+    assert_eq!(resp.rate_limit.reset_tokens, None);
+    assert_eq!(resp.raw_body, CHAT_RESPONSE);
+}
+
+#[test]
+fn raw_fields_are_added_to_the_body() {
+    let req = RequestBuilder::new(ChatModel::Gpt4, "key")
+        .messages(vec![ChatMessage::user("Hello")])
+        .raw_field("parallel_tool_calls", serde_json::json!(false))
+        .raw_field("max_completion_tokens", serde_json::json!(256))
+        .raw_field("model", serde_json::json!("gpt-4o"))
+        .build_chat();
+
+    let body: serde_json::Value = serde_json::from_str(req.body()).unwrap();
+    assert_eq!(body["parallel_tool_calls"], false);
+    assert_eq!(body["max_completion_tokens"], 256);
+    assert_eq!(body["model"], "gpt-4o");
+    assert_eq!(body["messages"][0]["content"], "Hello");
+}
+
+#[test]
+fn body_is_the_serialized_request() {
+    let req = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .messages(vec![ChatMessage::user("Hello")])
+        .temperature(0.5)
+        .build_chat();
+
+    let body: serde_json::Value = serde_json::from_str(req.body()).unwrap();
+    assert_eq!(body["model"], "gpt-3.5-turbo");
+    assert_eq!(body["messages"][0]["role"], "user");
+    assert_eq!(body["messages"][0]["content"], "Hello");
+    assert_eq!(body["temperature"], 0.5);
+    assert!(!req.body().contains("key"));
+}
+
+#[tokio::test]
+async fn requests_and_builders_can_be_cloned() {
+    let server = MockServer::start(vec![
+        MockResponse::json(200, CHAT_RESPONSE),
+        MockResponse::json(200, CHAT_RESPONSE),
+        MockResponse::json(200, CHAT_RESPONSE),
+    ])
+    .await;
+
+    let base = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .base_url(server.url())
+        .messages(vec![ChatMessage::user("Hello")]);
+    let req = base.clone().build_chat();
+
+    req.clone().send().await.unwrap();
+    req.send().await.unwrap();
+    base.temperature(0.5).build_chat().send().await.unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[0].json(), requests[1].json());
+    assert_eq!(requests[2].json()["temperature"], 0.5);
+}
+
+#[test]
+fn messages_can_be_added_from_iterators_and_one_by_one() {
+    let history = std::collections::VecDeque::from([
+        ChatMessage::system("You are a helpful assistant."),
+        ChatMessage::user("Hi"),
+    ]);
+
+    let req = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .messages(history.into_iter().take(1))
+        .add_message(ChatMessage::user("Hi"))
+        .add_message(ChatMessage::assistant("Hello!"))
+        .build_chat();
+    let body: serde_json::Value = serde_json::from_str(req.body()).unwrap();
+    let roles: Vec<_> = body["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|message| message["role"].as_str().unwrap())
+        .collect();
+    assert_eq!(roles, ["system", "user", "assistant"]);
+
+    let req = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .add_message(ChatMessage::user("Hi"))
+        .build_chat();
+    let body: serde_json::Value = serde_json::from_str(req.body()).unwrap();
+    assert_eq!(body["messages"][0]["content"], "Hi");
+}
@@ -0,0 +1,264 @@
+use rust_gpt::{
+    chat::{ChatChoice, ChatResponse, FinishReason, Usage},
+    completion::CompletionResponse,
+};
+
+const CHAT_RESPONSE: &str = r#"{
+    "id": "chatcmpl-123",
+    "object": "chat.completion",
+    "created": 1677652288,
+    "model": "gpt-3.5-turbo-0613",
+    "choices": [{
+        "index": 0,
+        "message": {"role": "assistant", "content": "Hello there, how may I assist you today?"},
+        "finish_reason": "stop"
+    }],
+    "usage": {"prompt_tokens": 9, "completion_tokens": 12, "total_tokens": 21}
+}"#;
+
+const COMPLETION_RESPONSE: &str = r#"{
+    "id": "cmpl-uqkvlQyYK7bGYrRHQ0eXlWi7",
+    "object": "text_completion",
+    "created": 1589478378,
+    "model": "text-davinci-003",
+    "choices": [{
+        "text": "This is indeed a test",
+        "index": 0,
+        "logprobs": null,
+        "finish_reason": "length"
+    }],
+    "usage": {"prompt_tokens": 5, "completion_tokens": 7, "total_tokens": 12}
+}"#;
+
+#[test]
+fn chat_response_from_str_and_value() {
+    let resp: ChatResponse = CHAT_RESPONSE.parse().unwrap();
+    assert_eq!(resp.id, "chatcmpl-123");
+    assert_eq!(resp.usage.total_tokens, 21);
+
+    let value: serde_json::Value = serde_json::from_str(CHAT_RESPONSE).unwrap();
+    let resp = ChatResponse::try_from(value).unwrap();
+    assert_eq!(resp.choices.len(), 1);
+}
+
+#[test]
+fn completion_response_from_str_and_value() {
+    let resp: CompletionResponse = COMPLETION_RESPONSE.parse().unwrap();
+    assert_eq!(resp.choices[0].text, "This is indeed a test");
+
+    let value: serde_json::Value = serde_json::from_str(COMPLETION_RESPONSE).unwrap();
+    let resp = CompletionResponse::try_from(value).unwrap();
+    assert_eq!(resp.model, "text-davinci-003");
+    assert_eq!(resp.usage.prompt_tokens, 5);
+    assert_eq!(resp.usage.completion_tokens, 7);
+    assert_eq!(resp.usage.total_tokens, 12);
+}
+
+#[test]
+fn invalid_response_is_an_error() {
+    assert!("{}".parse::<ChatResponse>().is_err());
+    assert!(CompletionResponse::try_from(serde_json::json!({"id": 1})).is_err());
+}
+
+#[test]
+fn usage_consistency() {
+    let usage = Usage {
+        prompt_tokens: 9,
+        completion_tokens: 12,
+        total_tokens: 21,
+    };
+    assert!(usage.is_consistent());
+    assert_eq!(usage.checked_total_tokens(), Some(21));
+
+    let usage = Usage {
+        total_tokens: 30,
+        ..usage
+    };
+    assert!(!usage.is_consistent());
+    assert_eq!(usage.checked_total_tokens(), None);
+}
+
+#[test]
+fn completion_logprobs_are_deserialized() {
+    let resp: CompletionResponse = r#"{
+        "id": "cmpl-7iA7iJjj8V2zOkCGvWF2hAkDWBQZe",
+        "object": "text_completion",
+        "created": 1690759702,
+        "model": "gpt-3.5-turbo-instruct",
+        "choices": [{
+            "text": " Hello world",
+            "index": 0,
+            "logprobs": {
+                "tokens": [" Hello", " world"],
+                "token_logprobs": [-0.31725305, -0.0017020052],
+                "top_logprobs": [{" Hello": -0.31725305}, {" world": -0.0017020052}],
+                "text_offset": [9, 15]
+            },
+            "finish_reason": "length"
+        }],
+        "usage": {"prompt_tokens": 4, "completion_tokens": 2, "total_tokens": 6}
+    }"#
+    .parse()
+    .unwrap();
+
+    let logprobs = resp.choices[0].logprobs.as_ref().unwrap();
+    assert_eq!(logprobs.tokens, [" Hello", " world"]);
+    assert_eq!(logprobs.token_logprobs[1], Some(-0.0017020052));
+    assert_eq!(
+        logprobs.top_logprobs[0].as_ref().unwrap()[" Hello"],
+        -0.31725305
+    );
+    assert_eq!(logprobs.text_offset, [9, 15]);
+}
+
+#[test]
+fn all_choices_can_be_collected() {
+    let resp: ChatResponse = r#"{
+        "id": "chatcmpl-123",
+        "object": "chat.completion",
+        "created": 1677652288,
+        "choices": [
+            {"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"},
+            {"index": 1, "message": {"role": "assistant", "content": "Hello"}, "finish_reason": "stop"},
+            {"index": 2, "message": {"role": "assistant", "content": "Hey"}, "finish_reason": "stop"}
+        ],
+        "usage": {"prompt_tokens": 9, "completion_tokens": 3, "total_tokens": 12}
+    }"#
+    .parse()
+    .unwrap();
+    let contents: Vec<_> = resp
+        .messages()
+        .into_iter()
+        .map(|message| message.text().unwrap())
+        .collect();
+    assert_eq!(contents, ["Hi", "Hello", "Hey"]);
+
+    let resp: CompletionResponse = r#"{
+        "id": "cmpl-123",
+        "object": "text_completion",
+        "created": 1589478378,
+        "model": "gpt-3.5-turbo-instruct",
+        "choices": [
+            {"text": "one", "index": 0, "logprobs": null, "finish_reason": "stop"},
+            {"text": "two", "index": 1, "logprobs": null, "finish_reason": "stop"},
+            {"text": "three", "index": 2, "logprobs": null, "finish_reason": "length"}
+        ],
+        "usage": {"prompt_tokens": 5, "completion_tokens": 3, "total_tokens": 8}
+    }"#
+    .parse()
+    .unwrap();
+    assert_eq!(resp.texts(), ["one", "two", "three"]);
+}
+
+#[test]
+fn first_choice_accessors() {
+    let resp: ChatResponse = CHAT_RESPONSE.parse().unwrap();
+    assert_eq!(
+        resp.first_message().unwrap().text(),
+        Some("Hello there, how may I assist you today?")
+    );
+
+    let resp: CompletionResponse = COMPLETION_RESPONSE.parse().unwrap();
+    assert_eq!(resp.first_text(), Some("This is indeed a test"));
+}
+
+#[test]
+fn first_choice_accessors_handle_empty_choices() {
+    let mut resp: ChatResponse = CHAT_RESPONSE.parse().unwrap();
+    resp.choices.clear();
+    assert!(resp.first_message().is_none());
+
+    let mut resp: CompletionResponse = COMPLETION_RESPONSE.parse().unwrap();
+    resp.choices.clear();
+    assert_eq!(resp.first_text(), None);
+}
+
+#[test]
+fn responses_display_their_first_choice() {
+    let mut resp: ChatResponse = CHAT_RESPONSE.parse().unwrap();
+    assert_eq!(resp.to_string(), "Hello there, how may I assist you today?");
+    resp.choices[0].message.content = None;
+    assert_eq!(resp.to_string(), "");
+    resp.choices.clear();
+    assert_eq!(resp.to_string(), "");
+
+    let mut resp: CompletionResponse = COMPLETION_RESPONSE.parse().unwrap();
+    assert_eq!(format!("{resp}"), "This is indeed a test");
+    resp.choices.clear();
+    assert_eq!(format!("{resp}"), "");
+}
+
+#[test]
+fn responses_from_compatible_servers_without_object_and_index_parse() {
+    let resp: ChatResponse = r#"{
+        "id": "chatcmpl-local",
+        "created": 1700000000,
+        "choices": [{"message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 3, "completion_tokens": 1, "total_tokens": 4}
+    }"#
+    .parse()
+    .unwrap();
+    assert_eq!(resp.object, "");
+    assert_eq!(resp.choices[0].index, 0);
+    assert_eq!(resp.to_string(), "Hi");
+
+    let resp: CompletionResponse = r#"{
+        "id": "cmpl-local",
+        "created": 1700000000,
+        "model": "llama-2-7b",
+        "choices": [{"text": "Hi", "finish_reason": "length"}],
+        "usage": {"prompt_tokens": 3, "completion_tokens": 1, "total_tokens": 4}
+    }"#
+    .parse()
+    .unwrap();
+    assert_eq!(resp.object, "");
+    assert_eq!(resp.choices[0].index, 0);
+    assert_eq!(resp.first_text(), Some("Hi"));
+}
+
+#[test]
+fn finish_reasons_are_parsed() {
+    let parse = |reason: &str| {
+        serde_json::from_value::<ChatChoice>(serde_json::json!({
+            "index": 0,
+            "message": {"role": "assistant", "content": ""},
+            "finish_reason": serde_json::from_str::<serde_json::Value>(reason).unwrap(),
+        }))
+        .unwrap()
+        .finish_reason
+    };
+
+    assert_eq!(parse(r#""stop""#), FinishReason::Stop);
+    assert_eq!(parse(r#""length""#), FinishReason::Length);
+    assert_eq!(parse(r#""content_filter""#), FinishReason::ContentFilter);
+    assert_eq!(parse(r#""function_call""#), FinishReason::FunctionCall);
+    assert_eq!(parse(r#""tool_calls""#), FinishReason::ToolCalls);
+    assert_eq!(parse("null"), FinishReason::Null);
+    assert_eq!(parse(r#""eos""#), FinishReason::Other("eos".to_string()));
+
+    let resp: CompletionResponse = COMPLETION_RESPONSE.parse().unwrap();
+    assert_eq!(resp.choices[0].finish_reason, FinishReason::Length);
+    assert_eq!(
+        serde_json::to_value(&resp.choices[0]).unwrap()["finish_reason"],
+        "length"
+    );
+}
+
+#[test]
+fn created_timestamp_converts_to_system_time() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let resp: ChatResponse = CHAT_RESPONSE.parse().unwrap();
+    assert_eq!(
+        resp.created_time(),
+        UNIX_EPOCH + Duration::from_secs(1677652288)
+    );
+    assert_eq!(
+        resp.created_time()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        resp.created
+    );
+    assert_eq!(resp.model, "gpt-3.5-turbo-0613");
+}
@@ -0,0 +1,39 @@
+use rust_gpt::responses::*;
+
+const RESPONSE: &str = r#"{
+    "id": "resp_67ccd2bed1ec8190b14f964abc054267",
+    "object": "response",
+    "created_at": 1741476542,
+    "status": "completed",
+    "error": null,
+    "model": "gpt-4o-2024-08-06",
+    "output": [
+        {
+            "type": "reasoning",
+            "id": "rs_1",
+            "summary": []
+        },
+        {
+            "type": "message",
+            "id": "msg_67ccd2bf17f0819081ff3bb2cf6508e6",
+            "status": "completed",
+            "role": "assistant",
+            "content": [
+                {"type": "output_text", "text": "In a peaceful grove, ", "annotations": []},
+                {"type": "output_text", "text": "a unicorn slept.", "annotations": []}
+            ]
+        }
+    ],
+    "previous_response_id": null,
+    "usage": {"input_tokens": 36, "output_tokens": 87, "total_tokens": 123}
+}"#;
+
+#[test]
+fn responses_result_deserializes() {
+    let resp: ResponsesResult = RESPONSE.parse().unwrap();
+
+    assert_eq!(resp.output.len(), 2);
+    assert!(matches!(resp.output[0], OutputItem::Other));
+    assert_eq!(resp.output_text(), "In a peaceful grove, a unicorn slept.");
+    assert_eq!(resp.usage.unwrap().total_tokens, 123);
+}
@@ -0,0 +1,400 @@
+mod common;
+
+use common::{MockResponse, MockServer};
+use futures_util::StreamExt;
+use rust_gpt::{chat::ChatMessage, *};
+
+fn sse(events: &[&str]) -> MockResponse {
+    let body: String = events
+        .iter()
+        .map(|event| format!("data: {event}\n\n"))
+        .collect();
+
+    MockResponse::event_stream(&body)
+}
+
+fn chat_chunk(delta: &str, finish_reason: &str) -> String {
+    format!(
+        r#"{{"id":"chatcmpl-123","object":"chat.completion.chunk","created":1694268190,"model":"gpt-3.5-turbo","choices":[{{"index":0,"delta":{delta},"finish_reason":{finish_reason}}}]}}"#
+    )
+}
+
+#[tokio::test]
+async fn chat_stream_yields_deltas_until_done() {
+    let server = MockServer::start(vec![sse(&[
+        &chat_chunk(r#"{"role":"assistant","content":""}"#, "null"),
+        &chat_chunk(r#"{"content":"Hello"}"#, "null"),
+        &chat_chunk(r#"{"content":" there"}"#, "null"),
+        &chat_chunk("{}", r#""stop""#),
+        "[DONE]",
+    ])])
+    .await;
+
+    let mut stream = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .base_url(server.url())
+        .messages(vec![ChatMessage::default()])
+        .stream(true)
+        .build_chat()
+        .send_stream()
+        .await
+        .unwrap();
+
+    let mut content = String::new();
+    let mut chunks = 0;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.unwrap();
+        content.push_str(
+            chunk.choices()[0]
+                .delta
+                .content
+                .as_deref()
+                .unwrap_or_default(),
+        );
+        chunks += 1;
+    }
+
+    assert_eq!(chunks, 4);
+    assert_eq!(content, "Hello there");
+    assert_eq!(server.requests()[0].json()["stream"], true);
+}
+
+#[tokio::test]
+async fn chat_stream_requires_streaming_to_be_enabled() {
+    let result = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .base_url("http://127.0.0.1:1")
+        .messages(vec![ChatMessage::default()])
+        .build_chat()
+        .send_stream()
+        .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn chat_stream_ends_with_usage_when_requested() {
+    let server = MockServer::start(vec![sse(&[
+        r#"{"id":"chatcmpl-123","object":"chat.completion.chunk","created":1694268190,"model":"gpt-3.5-turbo","choices":[{"index":0,"delta":{"role":"assistant","content":"Hi"},"finish_reason":null}],"usage":null}"#,
+        r#"{"id":"chatcmpl-123","object":"chat.completion.chunk","created":1694268190,"model":"gpt-3.5-turbo","choices":[{"index":0,"delta":{},"finish_reason":"stop"}],"usage":null}"#,
+        r#"{"id":"chatcmpl-123","object":"chat.completion.chunk","created":1694268190,"model":"gpt-3.5-turbo","choices":[],"usage":{"prompt_tokens":9,"completion_tokens":1,"total_tokens":10}}"#,
+        "[DONE]",
+    ])])
+    .await;
+
+    let chunks: Vec<_> = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .base_url(server.url())
+        .messages(vec![ChatMessage::default()])
+        .stream(true)
+        .stream_usage(true)
+        .try_build_chat()
+        .unwrap()
+        .send_stream()
+        .await
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+        .await;
+
+    assert_eq!(chunks.len(), 3);
+    assert!(matches!(chunks[0], chat::ChatStreamChunk::Delta(_)));
+    assert_eq!(chunks[1].choices().len(), 1);
+    match &chunks[2] {
+        chat::ChatStreamChunk::Usage(usage) => assert_eq!(usage.total_tokens, 10),
+        chunk => panic!("expected usage, got {chunk:?}"),
+    }
+    assert_eq!(
+        server.requests()[0].json()["stream_options"],
+        serde_json::json!({"include_usage": true})
+    );
+}
+
+#[test]
+fn stream_usage_requires_streaming() {
+    let build = |stream| {
+        RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+            .messages(vec![ChatMessage::default()])
+            .stream(stream)
+            .stream_usage(true)
+            .try_build_chat()
+    };
+
+    assert_eq!(
+        build(false).err(),
+        Some(BuildError::StreamOptionsWithoutStream)
+    );
+    assert!(build(true).is_ok());
+
+    let disabled = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .messages(vec![ChatMessage::default()])
+        .stream_usage(true)
+        .stream_usage(false)
+        .try_build_chat()
+        .unwrap();
+    assert!(!disabled.body().contains("stream_options"));
+}
+
+#[tokio::test]
+async fn streaming_mode_mismatches_are_rejected_before_sending() {
+    let server = MockServer::start(vec![MockResponse::json(200, "{}")]).await;
+    let builder = || {
+        RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+            .base_url(server.url())
+            .prompt("Say hi")
+    };
+
+    let err = builder()
+        .stream(true)
+        .build_completion()
+        .send()
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        SendRequestError::StreamingMismatch { streaming: true }
+    ));
+    assert!(err.to_string().contains("send_stream"));
+
+    let err = builder()
+        .stream(false)
+        .build_completion()
+        .send_stream()
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err,
+        SendRequestError::StreamingMismatch { streaming: false }
+    ));
+    assert!(err.to_string().contains("stream(true)"));
+
+    assert!(server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn completion_stream_handles_split_lines_and_keepalives() {
+    let chunk = |text: &str, finish_reason: &str| {
+        format!(
+            r#"data: {{"id":"cmpl-123","object":"text_completion","created":1694268190,"model":"gpt-3.5-turbo-instruct","choices":[{{"text":"{text}","index":0,"logprobs":null,"finish_reason":{finish_reason}}}]}}"#
+        )
+    };
+    let body = format!(
+        ": keep-alive\n\n{}\n\n\n{}\r\n\r\n{}\n\ndata: [DONE]\n\n",
+        chunk("Once", "null"),
+        chunk(" upon", "null"),
+        chunk(" a time", r#""length""#),
+    );
+    let server = MockServer::start(vec![MockResponse::event_stream(&body).chunk_size(7)]).await;
+
+    let stream = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .base_url(server.url())
+        .prompt("Tell me a story")
+        .stream(true)
+        .build_completion()
+        .send_stream()
+        .await
+        .unwrap();
+
+    let chunks: Vec<_> = stream.map(Result::unwrap).collect().await;
+    let text: String = chunks
+        .iter()
+        .map(|chunk| chunk.choices[0].text.as_str())
+        .collect();
+
+    assert_eq!(text, "Once upon a time");
+    assert_eq!(
+        chunks[2].choices[0].finish_reason.as_deref(),
+        Some("length")
+    );
+}
+
+#[tokio::test]
+async fn chat_streams_responses_into_a_channel_and_remembers_them() {
+    let server = MockServer::start(vec![sse(&[
+        &chat_chunk(r#"{"role":"assistant","content":""}"#, "null"),
+        &chat_chunk(r#"{"content":"Hello"}"#, "null"),
+        &chat_chunk(r#"{"content":" there"}"#, "null"),
+        &chat_chunk("{}", r#""stop""#),
+        "[DONE]",
+    ])])
+    .await;
+
+    let chat = chat::ChatBuilder::new(ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .build();
+    chat.ask("Hi").await.unwrap();
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+    let message = chat.stream_response(tx).await.unwrap();
+
+    let mut chunks = Vec::new();
+    while let Some(chunk) = rx.recv().await {
+        chunks.push(chunk);
+    }
+    assert_eq!(chunks, ["Hello", " there"]);
+    assert_eq!(message.text(), Some("Hello there"));
+    assert_eq!(server.requests()[0].json()["stream"], true);
+
+    let messages = chat.get_messages().await;
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[2].text(), Some("Hello there"));
+}
+
+#[tokio::test]
+async fn full_chat_streams_keep_the_finish_reason_and_usage() {
+    let server = MockServer::start(vec![sse(&[
+        &chat_chunk(r#"{"role":"assistant","content":"Once upon"}"#, "null"),
+        &chat_chunk(r#"{"content":" a time"}"#, "null"),
+        &chat_chunk("{}", r#""length""#),
+        r#"{"id":"chatcmpl-123","object":"chat.completion.chunk","created":1694268190,"model":"gpt-3.5-turbo","choices":[],"usage":{"prompt_tokens":9,"completion_tokens":4,"total_tokens":13}}"#,
+        "[DONE]",
+    ])])
+    .await;
+
+    let chat = chat::ChatBuilder::new(ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .build();
+    chat.ask("Tell me a story").await.unwrap();
+
+    let (tx, _rx) = tokio::sync::mpsc::channel(8);
+    let streamed = chat.stream_full_response(tx).await.unwrap();
+
+    assert_eq!(streamed.message.text(), Some("Once upon a time"));
+    assert_eq!(streamed.finish_reason, chat::FinishReason::Length);
+    assert_eq!(
+        streamed.usage,
+        Some(chat::Usage {
+            prompt_tokens: 9,
+            completion_tokens: 4,
+            total_tokens: 13,
+        })
+    );
+    assert_eq!(
+        chat.get_messages().await[2].text(),
+        Some("Once upon a time")
+    );
+}
+
+#[tokio::test]
+async fn streamed_tool_calls_are_joined_by_index() {
+    let server = MockServer::start(vec![sse(&[
+        &chat_chunk(
+            r#"{"role":"assistant","content":null,"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"get_weather","arguments":""}}]}"#,
+            "null",
+        ),
+        &chat_chunk(
+            r#"{"tool_calls":[{"index":1,"id":"call_2","type":"function","function":{"name":"get_time","arguments":"{}"}}]}"#,
+            "null",
+        ),
+        &chat_chunk(
+            r#"{"tool_calls":[{"index":0,"function":{"arguments":"{\"city\":"}}]}"#,
+            "null",
+        ),
+        &chat_chunk(
+            r#"{"tool_calls":[{"index":0,"function":{"arguments":"\"Paris\"}"}}]}"#,
+            "null",
+        ),
+        &chat_chunk("{}", r#""tool_calls""#),
+        "[DONE]",
+    ])])
+    .await;
+
+    let chat = chat::ChatBuilder::new(ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .build();
+    chat.ask("What's the weather and time in Paris?")
+        .await
+        .unwrap();
+
+    let (tx, _rx) = tokio::sync::mpsc::channel(8);
+    let streamed = chat.stream_full_response(tx).await.unwrap();
+    assert_eq!(streamed.finish_reason, chat::FinishReason::ToolCalls);
+
+    let calls = streamed.message.tool_calls.unwrap();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].id, "call_1");
+    assert_eq!(calls[0].tool_type, "function");
+    assert_eq!(calls[0].function.name, "get_weather");
+    assert_eq!(calls[0].function.arguments, r#"{"city":"Paris"}"#);
+    assert_eq!(calls[1].id, "call_2");
+    assert_eq!(calls[1].function.name, "get_time");
+    assert_eq!(calls[1].function.arguments, "{}");
+
+    let messages = chat.get_messages().await;
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[2].tool_calls.as_ref().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn incomplete_streamed_tool_calls_are_not_stored() {
+    let server = MockServer::start(vec![sse(&[
+        &chat_chunk(
+            r#"{"role":"assistant","tool_calls":[{"index":0,"function":{"arguments":"{}"}}]}"#,
+            r#""tool_calls""#,
+        ),
+        "[DONE]",
+    ])])
+    .await;
+
+    let chat = chat::ChatBuilder::new(ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .build();
+    chat.ask("What's the weather?").await.unwrap();
+
+    let (tx, _rx) = tokio::sync::mpsc::channel(8);
+    assert!(chat.stream_full_response(tx).await.is_err());
+    assert_eq!(chat.get_messages().await.len(), 1);
+}
+
+#[tokio::test]
+async fn streamed_usage_is_added_to_the_chat_total() {
+    let server = MockServer::start(vec![sse(&[
+        &chat_chunk(r#"{"role":"assistant","content":"Hi"}"#, r#""stop""#),
+        r#"{"id":"chatcmpl-123","object":"chat.completion.chunk","created":1694268190,"model":"gpt-3.5-turbo","choices":[],"usage":{"prompt_tokens":9,"completion_tokens":1,"total_tokens":10}}"#,
+        "[DONE]",
+    ])])
+    .await;
+
+    let chat = chat::ChatBuilder::new(ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .stream_usage(true)
+        .build();
+
+    for question in ["Hi", "Hi again"] {
+        chat.ask(question).await.unwrap();
+        let (tx, _rx) = tokio::sync::mpsc::channel(8);
+        chat.stream_response(tx).await.unwrap();
+    }
+
+    assert_eq!(
+        server.requests()[0].json()["stream_options"],
+        serde_json::json!({"include_usage": true})
+    );
+    assert_eq!(
+        chat.total_usage().await,
+        chat::Usage {
+            prompt_tokens: 18,
+            completion_tokens: 2,
+            total_tokens: 20,
+        }
+    );
+}
+
+#[tokio::test]
+async fn chat_stream_is_aborted_when_the_receiver_is_dropped() {
+    let server = MockServer::start(vec![sse(&[
+        &chat_chunk(r#"{"role":"assistant","content":"Hello"}"#, "null"),
+        &chat_chunk(r#"{"content":" there"}"#, "null"),
+        "[DONE]",
+    ])])
+    .await;
+
+    let chat = chat::ChatBuilder::new(ChatModel::Gpt35Turbo, "key".to_string())
+        .base_url(server.url())
+        .build();
+    chat.ask("Hi").await.unwrap();
+
+    let (tx, rx) = tokio::sync::mpsc::channel(8);
+    drop(rx);
+
+    assert!(chat.stream_response(tx).await.is_err());
+    assert_eq!(chat.get_messages().await.len(), 1);
+}
@@ -0,0 +1,44 @@
+use rust_gpt::{
+    chat::{ChatMessage, Role},
+    tokens::{count_message_tokens, count_tokens},
+};
+
+#[test]
+fn tokens_are_counted_with_the_model_tokenizer() {
+    assert_eq!(count_tokens("gpt-4", "tiktoken is great!"), 6);
+    assert_eq!(count_tokens("gpt-3.5-turbo", "hello world"), 2);
+    assert_eq!(count_tokens("gpt-4", ""), 0);
+    // unknown models fall back to cl100k_base
+    assert_eq!(count_tokens("my-fine-tune", "tiktoken is great!"), 6);
+}
+
+#[test]
+fn message_tokens_include_the_per_message_overhead() {
+    let messages = [
+        ChatMessage::system("You are a helpful assistant."),
+        ChatMessage::user("hello world"),
+    ];
+    let content: usize = messages
+        .iter()
+        .map(|msg| count_tokens("gpt-4", msg.text().unwrap()))
+        .sum();
+
+    // 3 per message, 1 for each role and 3 to prime the reply
+    assert_eq!(
+        count_message_tokens("gpt-4", &messages),
+        content + 2 * 4 + 3
+    );
+
+    let named = ChatMessage {
+        name: Some("example_user".to_string()),
+        ..ChatMessage::new(Role::User, "hello world")
+    };
+    assert_eq!(
+        count_message_tokens("gpt-4", std::slice::from_ref(&named)),
+        count_message_tokens("gpt-4", &messages[1..]) + count_tokens("gpt-4", "example_user") + 1
+    );
+    assert_eq!(
+        count_message_tokens("gpt-3.5-turbo-0301", &[named]),
+        count_message_tokens("gpt-4", &messages[1..]) + count_tokens("gpt-4", "example_user")
+    );
+}
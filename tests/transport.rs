@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use futures_util::StreamExt;
+use rust_gpt::{
+    chat::{ChatBuilder, ChatMessage},
+    transport::MockTransport,
+    *,
+};
+
+const CHAT_RESPONSE: &str = r#"{
+    "id": "chatcmpl-123",
+    "object": "chat.completion",
+    "created": 1677652288,
+    "choices": [{
+        "index": 0,
+        "message": {"role": "assistant", "content": "Hello there!"},
+        "finish_reason": "stop"
+    }],
+    "usage": {"prompt_tokens": 9, "completion_tokens": 3, "total_tokens": 12}
+}"#;
+
+#[tokio::test]
+async fn requests_are_sent_through_the_transport() {
+    let mock = Arc::new(MockTransport::new());
+    mock.respond(200, CHAT_RESPONSE);
+
+    let resp = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .organization("org-123")
+        .transport(mock.clone())
+        .messages(vec![ChatMessage::user("Hi")])
+        .build_chat()
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.first_message().unwrap().text(), Some("Hello there!"));
+
+    let requests = mock.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(
+        requests[0].url,
+        "https://api.openai.com/v1/chat/completions"
+    );
+    assert_eq!(requests[0].header("authorization"), Some("Bearer key"));
+    assert_eq!(requests[0].header("OpenAI-Organization"), Some("org-123"));
+    assert_eq!(requests[0].json()["messages"][0]["content"], "Hi");
+}
+
+#[tokio::test]
+async fn transport_errors_and_retries_behave_like_http() {
+    let mock = Arc::new(MockTransport::new());
+    mock.respond(503, "Service Unavailable")
+        .respond(
+            401,
+            r#"{"error": {"message": "Incorrect API key", "type": "invalid_request_error", "code": "invalid_api_key"}}"#,
+        );
+
+    let err = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .transport(mock.clone())
+        .retry(RetryConfig {
+            max_retries: 1,
+            base_delay: std::time::Duration::ZERO,
+        })
+        .messages(vec![ChatMessage::user("Hi")])
+        .build_chat()
+        .send()
+        .await
+        .unwrap_err();
+
+    match err {
+        SendRequestError::OpenAiError(e) => assert_eq!(e.code.as_deref(), Some("invalid_api_key")),
+        other => panic!("expected an OpenAI error, got {other}"),
+    }
+    assert_eq!(mock.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn streams_are_read_from_the_transport() {
+    let mock = Arc::new(MockTransport::new());
+    mock.respond(
+        200,
+        concat!(
+            r#"data: {"id":"1","object":"chat.completion.chunk","created":0,"model":"gpt-3.5-turbo","choices":[{"index":0,"delta":{"content":"Hi"},"finish_reason":null}]}"#,
+            "\n\n",
+            "data: [DONE]\n\n",
+        ),
+    );
+
+    let chunks: Vec<_> = RequestBuilder::new(ChatModel::Gpt35Turbo, "key")
+        .transport(mock)
+        .messages(vec![ChatMessage::user("Hi")])
+        .stream(true)
+        .build_chat()
+        .send_stream()
+        .await
+        .unwrap()
+        .collect()
+        .await;
+
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(
+        chunks[0].as_ref().unwrap().choices()[0]
+            .delta
+            .content
+            .as_deref(),
+        Some("Hi")
+    );
+}
+
+#[tokio::test]
+async fn chats_use_the_transport() {
+    let mock = Arc::new(MockTransport::new());
+    mock.respond(200, CHAT_RESPONSE);
+
+    let chat = ChatBuilder::new(ChatModel::Gpt35Turbo, "key".to_string())
+        .transport(mock.clone())
+        .header("OpenAI-Beta", "assistants=v2")
+        .build();
+
+    let reply = chat.send("Hi", None).await.unwrap();
+    assert_eq!(reply.text(), Some("Hello there!"));
+    assert_eq!(chat.total_usage().await.total_tokens, 12);
+    assert_eq!(mock.requests()[0].json()["messages"][1]["content"], "Hi");
+    assert_eq!(
+        mock.requests()[0].header("openai-beta"),
+        Some("assistants=v2")
+    );
+}
+
+#[tokio::test]
+async fn error_bodies_are_reported_by_every_endpoint() {
+    const ERROR: &str =
+        r#"{"error": {"message": "Bad input", "type": "invalid_request_error", "code": null}}"#;
+
+    let mock = Arc::new(MockTransport::new());
+    mock.respond(200, ERROR).respond(200, ERROR);
+
+    let err = RequestBuilder::new(CompletionModel::Gpt35TurboInstruct, "key")
+        .transport(mock.clone())
+        .prompt("Hi")
+        .build_completion()
+        .send()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, SendRequestError::OpenAiError(_)), "{err}");
+
+    let err = RequestBuilder::new(EmbeddingModel::TextEmbeddingAda002, "key")
+        .transport(mock)
+        .input("Hi")
+        .build_embedding()
+        .send()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, SendRequestError::OpenAiError(_)), "{err}");
+}